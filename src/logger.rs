@@ -64,20 +64,20 @@ fn log(message: String, level: i32) {
 #[macro_export]
 macro_rules! debug {
   ($($arg:tt)*) => {
-    crate::logger::debug(format!($($arg)*))
+    $crate::logger::debug(format!($($arg)*))
   }
 }
 
 #[macro_export]
 macro_rules! info {
   ($($arg:tt)*) => {
-    crate::logger::info(format!($($arg)*))
+    $crate::logger::info(format!($($arg)*))
   }
 }
 
 #[macro_export]
 macro_rules! error {
   ($($arg:tt)*) => {
-    crate::logger::error(format!($($arg)*))
+    $crate::logger::error(format!($($arg)*))
   }
 }
@@ -90,25 +90,33 @@ fn main() {
     Err(e) => panic!("Error reading instance file {}", e),
   };
 
-  instance.init(config.optimize_cost);
+  instance.init(config.objective());
   instance.validate().unwrap();
-  let result = runner::run(&config, &instance);
-  let sol;
-
-  match result {
-    Some(value) => sol = value,
+  let run_result = match runner::run(&config, &instance) {
+    Some(value) => value,
     None => {
       info!("No solution found");
       return;
     }
+  };
+
+  info!("{}", run_result.best);
+  info!("Repaired iterations: {}", run_result.repaired_iters);
+  if config.multi_objective {
+    info!("Pareto front of {} solutions", run_result.pareto_front.len());
   }
 
-  info!("{}", sol);
+  let vehicle_ids = std::iter::once(&run_result.best)
+    .chain(run_result.pareto_front.iter())
+    .flat_map(|sol| sol.routes.iter().map(|route| route.vehicle_id));
+  instance.add_created_vehicles(vehicle_ids);
 
   let output = Output {
     name: instance.name.clone(),
-    instance: instance,
-    solution: sol,
+    instance,
+    solution: run_result.best,
+    pareto_front: run_result.pareto_front,
+    repaired_iters: run_result.repaired_iters,
   };
 
   args.value_of("output_file").map(|path|
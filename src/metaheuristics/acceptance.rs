@@ -0,0 +1,64 @@
+use rand::Rng;
+
+use super::types::{AcceptanceCriterion, GraspConfig};
+
+///
+/// State of the acceptance criterion along a walk. Every criterion is expressed as the increase of the
+/// value the next move may have.
+pub struct Acceptance {
+  criterion: AcceptanceCriterion,
+  temperature: f64,
+  cooling: f64,
+  deviation: f64,
+  /* best value of the walk */
+  record: f64,
+  /* values of the last late_acceptance_length moves */
+  history: Vec<f64>,
+  moves: usize,
+}
+
+impl Acceptance {
+  ///
+  /// The late acceptance history starts with the initial_value of the improvement phase, as if the walk started
+  /// from the construction.
+  pub fn new(config: &GraspConfig, value: f64, initial_value: f64) -> Self {
+    Acceptance {
+      criterion: config.acceptance,
+      temperature: config.annealing_temperature * value.abs(),
+      cooling: config.annealing_cooling,
+      deviation: config.record_deviation,
+      record: value,
+      history: vec![initial_value.max(value); config.late_acceptance_length.max(1)],
+      moves: 0,
+    }
+  }
+
+  ///
+  /// Max increase over value accepted on the next move. The simulated annealing draws it so that an increase d
+  /// is accepted with probability exp(-d / temperature).
+  pub fn threshold(&self, value: f64) -> f64 {
+    match self.criterion {
+      AcceptanceCriterion::Descent => 0.0,
+      AcceptanceCriterion::SimulatedAnnealing => {
+        let uniform: f64 = 1.0 - rand::thread_rng().gen::<f64>();
+        -self.temperature * uniform.ln()
+      },
+      AcceptanceCriterion::RecordToRecord => self.record * (1.0 + self.deviation) - value,
+      AcceptanceCriterion::LateAcceptance => {
+        let late_value = self.history[self.moves % self.history.len()];
+        (late_value - value).max(0.0)
+      },
+    }
+  }
+
+  ///
+  /// Updates the state with the value after a move, whether it was accepted or not.
+  pub fn update(&mut self, value: f64) {
+    self.temperature *= self.cooling;
+    self.record = self.record.min(value);
+
+    let position = self.moves % self.history.len();
+    self.history[position] = value;
+    self.moves += 1;
+  }
+}
@@ -0,0 +1,454 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::types::{Cost, ProblemInstance, RouteEntryClient, Solution};
+
+use super::grasp::Grasp;
+use super::types::{ConstructionResult, GeneticConfig};
+
+///
+/// Individual of the population, a giant tour with every client and its educated solution.
+struct Individual {
+  tour: Vec<usize>,
+  sol: Solution,
+  /* clients before and after each client served on its route */
+  neighbors: Vec<Option<(usize, usize)>>,
+}
+
+impl Individual {
+  ///
+  /// The tour visits the clients in the order of the routes, followed by the unserved ones.
+  fn new(problem: &ProblemInstance, sol: Solution) -> Self {
+    let mut neighbors = vec![None; problem.clients.len()];
+    let mut tour = vec![];
+    let mut in_tour = vec![false; problem.clients.len()];
+
+    for route in sol.routes.iter() {
+      let visits: Vec<usize> = route.visits().iter().map(|rc| rc.client_id).collect();
+
+      for window in visits.windows(3).filter(|window| window[1] != problem.source) {
+        neighbors[window[1]] = Some((window[0], window[2]));
+      }
+
+      for client_id in visits.into_iter().filter(|client_id| *client_id != problem.source) {
+        if !in_tour[client_id] {
+          in_tour[client_id] = true;
+          tour.push(client_id);
+        }
+      }
+    }
+
+    tour.extend((0..problem.clients.len()).filter(|client_id| *client_id != problem.source && !in_tour[*client_id]));
+
+    Individual { tour, sol, neighbors }
+  }
+
+  ///
+  /// Broken pairs distance, the fraction of the clients whose next client on the route of self
+  /// isn't next to them on the routes of other.
+  fn distance(&self, other: &Individual) -> f64 {
+    let clients = self.neighbors.len().max(2) - 1;
+    let broken = self.neighbors.iter().zip(other.neighbors.iter())
+      .filter(|(neighbors, other_neighbors)| match (neighbors, other_neighbors) {
+        (Some((_, next)), Some((other_prev, other_next))) => next != other_prev && next != other_next,
+        (Some(_), None) => true,
+        _ => false,
+      })
+      .count();
+
+    broken as f64 / clients as f64
+  }
+}
+
+///
+/// Hybrid genetic search: the population evolves by the order crossover of giant tours, which are split into
+/// routes and educated by the GRASP local search. Survivors are selected by a fitness biased by their
+/// contribution to the diversity of the population.
+pub struct Genetic {
+  pub config: GeneticConfig,
+  pub grasp: Grasp,
+  population: Vec<Individual>,
+  /* individuals built before the first crossover */
+  seeded: usize,
+}
+
+impl Genetic {
+  pub fn new(config: GeneticConfig, grasp: Grasp) -> Self {
+    Genetic { config, grasp, population: vec![], seeded: 0 }
+  }
+
+  ///
+  /// Adds an individual to the population and returns its solution. The first population_size ones are built
+  /// by the GRASP, the first grasp_seeds, or split from random giant tours. The rest are offspring of two
+  /// parents. When the population reaches population_size + generation_size the survivors are selected.
+  pub fn iterate(&mut self, problem: &ProblemInstance) -> Result<Solution, String> {
+    let sol = if self.seeded < self.config.population_size {
+      let sol = self.initial_solution(problem)?;
+      self.seeded += 1;
+      sol
+    } else {
+      self.offspring(problem)?
+    };
+
+    self.population.push(Individual::new(problem, sol.clone()));
+
+    if self.population.len() >= self.config.population_size + self.config.generation_size {
+      self.select_survivors();
+    }
+
+    Ok(sol)
+  }
+
+  fn initial_solution(&self, problem: &ProblemInstance) -> Result<Solution, String> {
+    if self.seeded < self.config.grasp_seeds {
+      return self.grasp.iterate(problem)
+    }
+
+    let mut tour: Vec<usize> = (0..problem.clients.len()).filter(|client_id| *client_id != problem.source).collect();
+    tour.shuffle(&mut rand::thread_rng());
+
+    self.split_solution(problem, &tour, "random tour")
+  }
+
+  fn offspring(&self, problem: &ProblemInstance) -> Result<Solution, String> {
+    if self.population.len() < 2 {
+      return Err("Not enough individuals to cross".to_string())
+    }
+
+    let fitness = self.biased_fitness();
+    let parent1 = &self.population[tournament(&fitness)];
+    let parent2 = &self.population[tournament(&fitness)];
+    let tour = order_crossover(&parent1.tour, &parent2.tour);
+
+    self.split_solution(problem, &tour, "genetic")
+  }
+
+  ///
+  /// Splits the tour and educates its solution by the GRASP local search. Clients that must be served
+  /// left unserved by the split, because the fleet isn't enough, are repaired like a GRASP construction.
+  fn split_solution(&self, problem: &ProblemInstance, tour: &[usize], name: &str) -> Result<Solution, String> {
+    let result = split(problem, tour, self.grasp.config.repair_penalty, self.config.split_labels.max(1))
+      .ok_or_else(|| "Couldn't split the tour".to_string())?;
+    let sol = self.grasp.construction_solution(result, name.to_string(), problem)?;
+
+    self.grasp.improve(sol, problem)
+  }
+
+  ///
+  /// Fitness of each individual, lower is better. Adds the rank by value and the rank by diversity contribution,
+  /// the average distance to its close_size closest individuals, weighted by 1 - elite_size / population.
+  fn biased_fitness(&self) -> Vec<f64> {
+    let size = self.population.len();
+    if size < 2 {
+      return vec![0.0; size]
+    }
+
+    let close_size = self.config.close_size.clamp(1, size - 1);
+    let contributions: Vec<f64> = self.population.iter().enumerate().map(|(index, individual)| {
+      let mut distances: Vec<f64> = self.population.iter().enumerate()
+        .filter(|(other_index, _)| *other_index != index)
+        .map(|(_, other)| individual.distance(other))
+        .collect();
+      distances.sort_unstable_by(|d1, d2| d1.partial_cmp(d2).unwrap());
+
+      distances[..close_size].iter().sum::<f64>() / close_size as f64
+    }).collect();
+
+    let mut by_value: Vec<usize> = (0..size).collect();
+    by_value.sort_by(|i1, i2| compare_solutions(&self.population[*i1].sol, &self.population[*i2].sol));
+
+    let mut by_diversity: Vec<usize> = (0..size).collect();
+    by_diversity.sort_by(|i1, i2| contributions[*i2].partial_cmp(&contributions[*i1]).unwrap());
+
+    let diversity_weight = 1.0 - self.config.elite_size as f64 / size as f64;
+    let mut fitness = vec![0.0; size];
+
+    for (rank, index) in by_value.into_iter().enumerate() {
+      fitness[index] += rank as f64 / (size - 1) as f64;
+    }
+    for (rank, index) in by_diversity.into_iter().enumerate() {
+      fitness[index] += diversity_weight * rank as f64 / (size - 1) as f64;
+    }
+
+    fitness
+  }
+
+  ///
+  /// Removes individuals until population_size are left, the clones of another individual first
+  /// and then the worst biased fitness.
+  fn select_survivors(&mut self) {
+    while self.population.len() > self.config.population_size {
+      let fitness = self.biased_fitness();
+      let is_clone = |index: usize| {
+        self.population.iter().enumerate()
+          .any(|(other_index, other)| other_index != index && self.population[index].distance(other) == 0.0)
+      };
+
+      let worst = (0..self.population.len())
+        .map(|index| (is_clone(index), fitness[index], index))
+        .max_by(|(clone1, fitness1, _), (clone2, fitness2, _)| {
+          clone1.cmp(clone2).then(fitness1.partial_cmp(fitness2).unwrap())
+        })
+        .map(|(_, _, index)| index)
+        .unwrap();
+
+      self.population.swap_remove(worst);
+    }
+  }
+}
+
+fn compare_solutions(sol1: &Solution, sol2: &Solution) -> Ordering {
+  if sol1.is_better_than(sol2) {
+    Ordering::Less
+  } else if sol2.is_better_than(sol1) {
+    Ordering::Greater
+  } else {
+    Ordering::Equal
+  }
+}
+
+///
+/// Index of the better fitness of two individuals chosen at random.
+fn tournament(fitness: &[f64]) -> usize {
+  let mut rng = rand::thread_rng();
+  let index1 = rng.gen_range(0, fitness.len());
+  let index2 = rng.gen_range(0, fitness.len());
+
+  if fitness[index1] <= fitness[index2] { index1 } else { index2 }
+}
+
+///
+/// Copies a random segment of tour1 and fills the rest with the clients left in the order of tour2,
+/// starting after the segment.
+fn order_crossover(tour1: &[usize], tour2: &[usize]) -> Vec<usize> {
+  let size = tour1.len();
+  if size < 2 {
+    return tour1.to_vec()
+  }
+
+  let mut rng = rand::thread_rng();
+  let (mut start, mut end) = (rng.gen_range(0, size), rng.gen_range(0, size));
+  if start > end {
+    std::mem::swap(&mut start, &mut end);
+  }
+
+  let mut child = tour1.to_vec();
+  let taken: HashSet<usize> = tour1[start..=end].iter().cloned().collect();
+  let rest = (0..size).map(|offset| tour2[(end + 1 + offset) % size]).filter(|client_id| !taken.contains(client_id));
+
+  for (offset, client_id) in rest.enumerate() {
+    child[(end + 1 + offset) % size] = client_id;
+  }
+
+  child
+}
+
+///
+/// Label of the split, the cost and vehicles used of each type to serve the tour up to a position.
+struct Label {
+  cost: Cost,
+  used: Vec<usize>,
+  /* previous position and label, and the vehicle type of the routes from there or None if the client is unserved */
+  pred: Option<(usize, usize, Option<usize>)>,
+}
+
+impl Label {
+  fn dominates(&self, other: &Label) -> bool {
+    self.cost <= other.cost && self.used.iter().zip(other.used.iter()).all(|(used, other_used)| used <= other_used)
+  }
+}
+
+///
+/// Adds the label if no other dominates it, removing the ones it dominates. Only the max_labels cheapest
+/// labels are kept.
+fn add_label(labels: &mut Vec<Label>, label: Label, max_labels: usize) {
+  if labels.iter().any(|other| other.dominates(&label)) {
+    return
+  }
+
+  labels.retain(|other| !label.dominates(other));
+  labels.push(label);
+
+  if labels.len() > max_labels {
+    let costliest = labels.iter()
+      .enumerate()
+      .max_by(|(_, l1), (_, l2)| l1.cost.partial_cmp(&l2.cost).unwrap())
+      .map(|(index, _)| index);
+    if let Some(index) = costliest {
+      labels.swap_remove(index);
+    }
+  }
+}
+
+///
+/// With split deliveries, the visits of client_id when its demand exceeds the capacity of vehicle_id, each one
+/// delivering a full load but the last like the constructions do. None if the demand fits, and no visits if
+/// vehicle_id can't carry any of it.
+fn oversized_visits(problem: &ProblemInstance, vehicle_id: usize, client_id: usize) -> Option<Vec<RouteEntryClient>> {
+  let mut demand_left = problem.clients[client_id].demand.clone();
+  if !problem.split_deliveries || demand_left.fits_in(&problem.vehicle(vehicle_id).capacity) {
+    return None
+  }
+
+  let mut visits = vec![];
+  while !demand_left.is_zero() {
+    let delivered = problem.split_delivery(vehicle_id, &demand_left);
+    if delivered.is_zero() {
+      return Some(vec![])
+    }
+
+    demand_left.sub(&delivered);
+    visits.push(RouteEntryClient { delivered, ..problem.visit(client_id) });
+  }
+
+  Some(visits)
+}
+
+///
+/// Splits the giant tour into the routes that minimize the solution value. Each route serves consecutive
+/// clients of the tour with a vehicle type that has vehicles left, and clients may be left unserved paying
+/// their prize, or unserved_penalty if they must be served. Clients whose demand exceeds the capacity of a
+/// vehicle type are served alone by as many routes of that type as their oversized_visits. The labels of each
+/// position of the tour keep the cost and the vehicles used of each type not dominated, up to the max_labels
+/// cheapest ones.
+fn split(problem: &ProblemInstance, tour: &[usize], unserved_penalty: Cost, max_labels: usize) -> Option<ConstructionResult> {
+  let type_vehicles = problem.type_vehicles();
+
+  let mut labels: Vec<Vec<Label>> = (0..=tour.len()).map(|_| vec![]).collect();
+  labels[0].push(Label { cost: 0 as Cost, used: vec![0; type_vehicles.len()], pred: None });
+
+  for start in 0..tour.len() {
+    /* Routes from start, as long as they are feasible */
+    let mut segments = vec![];
+
+    for (vehicle_type, vehicles) in type_vehicles.iter().enumerate() {
+      let Some(&vehicle_id) = vehicles.first() else { continue };
+
+      if let Some(split_visits) = oversized_visits(problem, vehicle_id, tour[start]) {
+        let routes: Option<Vec<Cost>> = split_visits.into_iter()
+          .map(|visit| problem.build_route(vehicle_id, &[problem.visit(problem.source), visit, problem.visit(problem.source)]))
+          .map(|route| route.map(|route| route.route_cost()))
+          .collect();
+        if let Some(costs) = routes.filter(|costs| !costs.is_empty()) {
+          segments.push((start + 1, vehicle_type, costs.len(), costs.iter().sum()));
+        }
+        continue
+      }
+
+      let mut visits = vec![problem.visit(problem.source)];
+
+      for (end, client_id) in tour.iter().enumerate().skip(start) {
+        visits.push(problem.visit(*client_id));
+        visits.push(problem.visit(problem.source));
+
+        match problem.build_route(vehicle_id, &visits) {
+          Some(route) => segments.push((end + 1, vehicle_type, 1, route.route_cost())),
+          None => break,
+        }
+
+        visits.pop();
+      }
+    }
+
+    let prize = problem.clients[tour[start]].prize.unwrap_or(unserved_penalty);
+    let start_labels: Vec<(Cost, Vec<usize>)> = labels[start].iter().map(|label| (label.cost, label.used.clone())).collect();
+
+    for (label_index, (cost, used)) in start_labels.into_iter().enumerate() {
+      add_label(&mut labels[start + 1], Label { cost: cost + prize, used: used.clone(), pred: Some((start, label_index, None)) }, max_labels);
+
+      for (end, vehicle_type, vehicle_count, route_cost) in segments.iter() {
+        if used[*vehicle_type] + vehicle_count <= type_vehicles[*vehicle_type].len() {
+          let mut used = used.clone();
+          used[*vehicle_type] += vehicle_count;
+          add_label(&mut labels[*end], Label { cost: cost + route_cost, used, pred: Some((start, label_index, Some(*vehicle_type))) }, max_labels);
+        }
+      }
+    }
+  }
+
+  let mut label = labels[tour.len()].iter().min_by(|l1, l2| l1.cost.partial_cmp(&l2.cost).unwrap())?;
+  let mut next_vehicle = vec![0; type_vehicles.len()];
+  let mut result = ConstructionResult::new(problem);
+  let mut end = tour.len();
+
+  while let Some((start, label_index, vehicle_type)) = label.pred {
+    if let Some(vehicle_type) = vehicle_type {
+      let first_vehicle = type_vehicles[vehicle_type][next_vehicle[vehicle_type]];
+      let routes_visits: Vec<Vec<RouteEntryClient>> = match oversized_visits(problem, first_vehicle, tour[start]) {
+        Some(split_visits) => split_visits.into_iter()
+          .map(|visit| vec![problem.visit(problem.source), visit, problem.visit(problem.source)])
+          .collect(),
+        None => vec![
+          std::iter::once(problem.source)
+            .chain(tour[start..end].iter().cloned())
+            .chain(std::iter::once(problem.source))
+            .map(|client_id| problem.visit(client_id))
+            .collect()
+        ],
+      };
+
+      for visits in routes_visits {
+        let vehicle_id = type_vehicles[vehicle_type][next_vehicle[vehicle_type]];
+        next_vehicle[vehicle_type] += 1;
+        let route = problem.build_route(vehicle_id, &visits)?;
+
+        for visit in visits.iter().filter(|visit| visit.client_id != problem.source) {
+          result.deliver(visit.client_id, &visit.delivered);
+        }
+        result.routes.push(route);
+      }
+    }
+
+    end = start;
+    label = &labels[start][label_index];
+  }
+
+  result.routes.reverse();
+
+  Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use crate::types::test_instances::instance;
+  use super::*;
+
+  #[test]
+  fn add_label_keeps_the_cheapest_labels() {
+    let mut labels = vec![];
+
+    for used in 0..5 {
+      add_label(&mut labels, Label { cost: (10 - used) as Cost, used: vec![used], pred: None }, 3);
+    }
+    add_label(&mut labels, Label { cost: 20 as Cost, used: vec![5], pred: None }, 3);
+
+    let mut costs: Vec<Cost> = labels.iter().map(|label| label.cost).collect();
+    costs.sort_by(|c1, c2| c1.partial_cmp(c2).unwrap());
+    assert_eq!(costs, vec![6 as Cost, 7 as Cost, 8 as Cost]);
+  }
+
+  #[test]
+  fn split_serves_clients_exceeding_the_capacity_by_several_routes() {
+    let problem = instance(json!({
+      "split_deliveries": true,
+      "vehicle_definitions": [{ "count": 3 }],
+      "clients": [{}, { "demand": 25 }],
+    }), &[0.0, 1.0]);
+
+    let result = split(&problem, &[1], 10000.0, 10).unwrap();
+    let deliveries: Vec<f64> = result.routes.iter()
+      .flat_map(|route| route.visits())
+      .filter(|visit| visit.client_id == 1)
+      .map(|visit| visit.delivered.0[0])
+      .collect();
+
+    assert!(result.unrouted.is_empty());
+    assert_eq!(result.routes.len(), 3);
+    assert_eq!(deliveries.iter().sum::<f64>(), 25.0);
+  }
+}
@@ -1,8 +1,11 @@
 use std::collections::{HashSet, HashMap};
 use std::iter::Iterator;
 
+use rand::Rng;
+
 use crate::types::{
   Cost,
+  Load,
   ProblemInstance,
   RouteEntry,
   Solution,
@@ -12,17 +15,37 @@ use crate::utils::time_max;
 use super::utils::{
   alpha_rcl_choose,
   alpha_max_index,
+  replace_route,
   transform_solution,
   weighted_choose,
 };
 use super::local_search::{LocalSearch, LocalSearchNotFound};
 use super::insertion_search::insertion_search;
 use super::opt2_search::opt2_search;
-use super::types::{GraspConfig, GraspWeightConfig, GraspRouteMove, GraspRoute};
+use super::prize_search::{drop_search, insert_search, swap_search};
+use super::i1_construction::i1_construction;
+use super::savings_construction::savings_construction;
+use super::regret_construction::regret_construction;
+use super::sweep_construction::sweep_construction;
+use super::repair::repair;
+use super::acceptance::Acceptance;
+use super::tabu_search::TabuList;
+use super::types::{
+  AcceptanceCriterion,
+  ConstructionResult,
+  GraspConfig,
+  GraspConstruction,
+  GraspImprovement,
+  GraspWeightConfig,
+  GraspRouteMove,
+  GraspRoute,
+  MoveCriteria,
+  Penalties,
+};
 
 
 #[derive(Debug, Clone)]
-pub struct BasicMove(usize, f64);
+pub struct BasicMove(usize, f64, bool, Load);
 
 pub struct Grasp {
   pub config: GraspConfig,
@@ -30,14 +53,53 @@ pub struct Grasp {
 
 impl Grasp {
   pub fn iterate(&self, problem: &ProblemInstance) -> Result<Solution, String> {
-    match self.build_solution(problem) {
-      Err(err) => Err(err),
-      Ok(initial_sol) => self.local_search(initial_sol, problem),
+    let initial_sol = self.build_solution(problem)?;
+
+    self.improve(initial_sol, problem)
+  }
+
+  ///
+  /// Applies the improvement method to a solution built by construction_solution.
+  pub fn improve(&self, initial_sol: Solution, problem: &ProblemInstance) -> Result<Solution, String> {
+    let sol = match self.config.improvement {
+      GraspImprovement::LocalSearch => self.local_search(initial_sol, problem)?,
+      GraspImprovement::TabuSearch => self.tabu_search(initial_sol, problem)?,
+    };
+
+    /* Clients the repair left unserved must be served by the local search */
+    if sol.unserved_clients.iter().any(|client_id| problem.clients[*client_id].prize.is_none()) {
+      return Err("Couldn't repair the solution".to_string())
     }
+
+    Ok(sol)
   }
 
-  fn insertion_local_search(&self, sol: &Solution, problem: &ProblemInstance) -> Option<Solution> {
-    let ls = LocalSearch::new(self.config.local_search_first_improvement);
+  ///
+  /// Evaluates the solution, the clients that must be served and are left unserved by the repair
+  /// are penalized with the repair_penalty.
+  fn evaluate_sol(&self, problem: &ProblemInstance, sol: &mut Solution) {
+    problem.evaluate_sol(sol);
+
+    let required_unserved = sol.unserved_clients.iter()
+      .filter(|client_id| problem.clients[**client_id].prize.is_none())
+      .count();
+    let penalty = required_unserved as Cost * self.config.repair_penalty;
+
+    sol.unserved_penalty += penalty;
+    sol.value += penalty;
+  }
+
+  ///
+  /// Applies the insertion search between every pair of routes. With best_move it returns the best move of
+  /// all of them, instead of following the configured first improvement strategies.
+  fn insertion_local_search(
+    &self,
+    sol: &Solution,
+    problem: &ProblemInstance,
+    criteria: &MoveCriteria,
+    best_move: bool,
+  ) -> Option<Solution> {
+    let ls = LocalSearch::new(self.config.local_search_first_improvement && !best_move);
 
     ls.iterate(&sol.routes, &sol.routes, |_index1, route1, _index2, route2| {
       if route1.vehicle_id == route2.vehicle_id {
@@ -49,13 +111,14 @@ impl Grasp {
         route1,
         route2,
         self.config.insertion_search_sequence_length,
-        self.config.insertion_search_first_improvement
+        criteria,
+        self.config.insertion_search_first_improvement && !best_move,
       );
 
       if let Some((new_route1, new_route2)) = local_search_result {
         let mut new_sol = transform_solution(sol, &new_route1, &new_route2);
-        problem.evaluate_sol(&mut new_sol);
-        let ret_val = new_sol.value;
+        self.evaluate_sol(problem, &mut new_sol);
+        let ret_val = criteria.penalties.map_or(new_sol.value, |penalties| penalties.solution_cost(&new_sol));
 
         Ok((new_sol, ret_val))
       } else {
@@ -64,8 +127,16 @@ impl Grasp {
     })
   }
 
-  fn opt2_local_search(&self, sol: &Solution, problem: &ProblemInstance) -> Option<Solution> {
-    let ls = LocalSearch::new(self.config.local_search_first_improvement);
+  ///
+  /// Applies the opt2 search between every pair of routes, like insertion_local_search.
+  fn opt2_local_search(
+    &self,
+    sol: &Solution,
+    problem: &ProblemInstance,
+    criteria: &MoveCriteria,
+    best_move: bool,
+  ) -> Option<Solution> {
+    let ls = LocalSearch::new(self.config.local_search_first_improvement && !best_move);
 
     ls.iterate(&sol.routes, &sol.routes, |_index1, route1, _index2, route2| {
       if route1.vehicle_id == route2.vehicle_id {
@@ -73,12 +144,12 @@ impl Grasp {
       }
 
       let local_search_result = opt2_search(
-        problem, route1, route2, self.config.opt2_search_first_improvement
+        problem, route1, route2, criteria, self.config.opt2_search_first_improvement && !best_move
       );
       if let Some((new_route1, new_route2)) = local_search_result {
         let mut new_sol = transform_solution(sol, &new_route1, &new_route2);
-        problem.evaluate_sol(&mut new_sol);
-        let value = new_sol.value;
+        self.evaluate_sol(problem, &mut new_sol);
+        let value = criteria.penalties.map_or(new_sol.value, |penalties| penalties.solution_cost(&new_sol));
   
         Ok((new_sol, value))
       } else {
@@ -87,7 +158,317 @@ impl Grasp {
     })
   }
 
+  ///
+  /// Inserts unserved optional clients or drops served ones, whatever improves the solution the most.
+  ///
+  /// Replaces a served optional client with an unserved one of higher priority, regardless of the cost.
+  fn priority_local_search(&self, sol: &Solution, problem: &ProblemInstance) -> Option<Solution> {
+    let ls = LocalSearch::new(self.config.local_search_first_improvement);
+
+    ls.iterate(&sol.unserved_clients, &sol.routes, |index1, client_id, _index2, route| {
+      let local_search_result = swap_search(
+        problem, route, *client_id, self.config.prize_search_first_improvement
+      );
+
+      if let Some((new_route, removed_client_id)) = local_search_result {
+        let mut new_sol = replace_route(sol, &new_route);
+        new_sol.unserved_clients[index1] = removed_client_id;
+        new_sol.unserved_clients.sort_unstable();
+        self.evaluate_sol(problem, &mut new_sol);
+        let value = new_sol.value;
+
+        Ok((new_sol, value))
+      } else {
+        Err(LocalSearchNotFound)
+      }
+    })
+  }
+
+  fn prize_local_search(&self, sol: &Solution, problem: &ProblemInstance) -> Option<Solution> {
+    let ls = LocalSearch::new(self.config.local_search_first_improvement);
+
+    let dropped_sol = ls.iterate_single(&sol.routes, |_index, route| {
+      let local_search_result = drop_search(
+        problem, route, self.config.prize_search_first_improvement
+      );
+
+      if let Some((new_route, client_id)) = local_search_result {
+        let mut new_sol = replace_route(sol, &new_route);
+        new_sol.unserved_clients.push(client_id);
+        self.evaluate_sol(problem, &mut new_sol);
+        let value = new_sol.value;
+
+        Ok((new_sol, value))
+      } else {
+        Err(LocalSearchNotFound)
+      }
+    });
+
+    let mut routes = sol.routes.clone();
+    routes.extend(problem.unused_vehicle_routes(&sol.routes));
+
+    let inserted_sol = ls.iterate(&sol.unserved_clients, &routes, |index1, client_id, _index2, route| {
+      let penalty = problem.clients[*client_id].prize.unwrap_or(self.config.repair_penalty);
+      let local_search_result = insert_search(
+        problem, route, *client_id, penalty, self.config.prize_search_first_improvement
+      );
+
+      if let Some(new_route) = local_search_result {
+        let mut new_sol = replace_route(sol, &new_route);
+        new_sol.unserved_clients.remove(index1);
+        self.evaluate_sol(problem, &mut new_sol);
+        let value = new_sol.value;
+
+        Ok((new_sol, value))
+      } else {
+        Err(LocalSearchNotFound)
+      }
+    });
+
+    match (dropped_sol, inserted_sol) {
+      (Some(dropped), Some(inserted)) => Some(if dropped.value < inserted.value { dropped } else { inserted }),
+      (dropped, inserted) => dropped.or(inserted),
+    }
+  }
+
   fn local_search(&self, sol: Solution, problem: &ProblemInstance) -> Result<Solution, String> {
+    let mut best_sol = self.descent(sol, problem)?;
+
+    if self.config.acceptance != AcceptanceCriterion::Descent {
+      let new_sol = self.acceptance_search(&best_sol, problem);
+
+      if new_sol.is_better_than(&best_sol) {
+        best_sol = self.descent(new_sol, problem)?;
+      }
+    }
+
+    if self.config.oscillation_enabled {
+      match self.oscillation_search(&best_sol, problem) {
+        Some(new_sol) if new_sol.is_better_than(&best_sol) => self.descent(new_sol, problem),
+        _ => Ok(best_sol),
+      }
+    } else {
+      Ok(best_sol)
+    }
+  }
+
+  ///
+  /// Walk of acceptance_iters moves from the local optimum. Each move is the best opt2 or insertion move between
+  /// a random pair of routes, accepted if it increases the value less than the threshold of the acceptance
+  /// criterion. Returns the best solution of the walk.
+  fn acceptance_search(&self, sol: &Solution, problem: &ProblemInstance) -> Solution {
+    let mut acceptance = Acceptance::new(&self.config, sol.value, sol.construction_value);
+    let mut current = sol.clone();
+    let mut best_sol = sol.clone();
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..self.config.acceptance_iters {
+      let routes_count = current.routes.len();
+      if routes_count < 2 {
+        break
+      }
+
+      let criteria = MoveCriteria { threshold: acceptance.threshold(current.value), ..Default::default() };
+      let index1 = rng.gen_range(0, routes_count);
+      let index2 = (index1 + rng.gen_range(1, routes_count)) % routes_count;
+      let (route1, route2) = (&current.routes[index1], &current.routes[index2]);
+
+      let opt2_move = self.config.opt2_search_enabled && (!self.config.insertion_search_enabled || rng.gen::<bool>());
+      let result = if opt2_move {
+        opt2_search(problem, route1, route2, &criteria, self.config.opt2_search_first_improvement)
+      } else if self.config.insertion_search_enabled {
+        insertion_search(
+          problem,
+          route1,
+          route2,
+          self.config.insertion_search_sequence_length,
+          &criteria,
+          self.config.insertion_search_first_improvement,
+        )
+      } else {
+        None
+      };
+
+      if let Some((new_route1, new_route2)) = result {
+        current = transform_solution(&current, &new_route1, &new_route2);
+        self.evaluate_sol(problem, &mut current);
+
+        if current.is_better_than(&best_sol) {
+          best_sol = current.clone();
+        }
+      }
+
+      acceptance.update(current.value);
+    }
+
+    best_sol
+  }
+
+  ///
+  /// Tabu search through the opt2 and insertion neighbourhoods. Each move is the best one between any pair of
+  /// routes, even if it increases the value, that restores no arc nor client to vehicle assignment removed in
+  /// the last tabu_tenure moves, unless it reaches a new best value. Ends with a descent from the best solution
+  /// found, which also applies the prize searches.
+  fn tabu_search(&self, sol: Solution, problem: &ProblemInstance) -> Result<Solution, String> {
+    let mut tabu = TabuList::new(self.config.tabu_tenure);
+    let mut current = sol.clone();
+    let mut best_sol = sol;
+
+    for _ in 0..self.config.tabu_iters {
+      let criteria = MoveCriteria { threshold: f64::INFINITY, tabu: Some(&tabu), ..Default::default() };
+
+      let opt2_sol = if self.config.opt2_search_enabled {
+        self.opt2_local_search(&current, problem, &criteria, true)
+      } else {
+        None
+      };
+      let insertion_sol = if self.config.insertion_search_enabled {
+        self.insertion_local_search(&current, problem, &criteria, true)
+      } else {
+        None
+      };
+
+      let new_sol = match (opt2_sol, insertion_sol) {
+        (Some(opt2_sol), Some(insertion_sol)) if insertion_sol.value < opt2_sol.value => insertion_sol,
+        (Some(opt2_sol), _) => opt2_sol,
+        (None, Some(insertion_sol)) => insertion_sol,
+        (None, None) => break,
+      };
+
+      if new_sol.is_better_than(&best_sol) {
+        best_sol = new_sol.clone();
+      }
+
+      tabu.add_move(&current, &new_sol, best_sol.value);
+      current = new_sol;
+    }
+
+    self.descent(best_sol, problem)
+  }
+
+  ///
+  /// Strategic oscillation, a descent through relaxed routes that may exceed the capacities and time windows
+  /// paying a penalty for it. Every oscillation_update_iters moves, the penalty of each kind of violation is
+  /// increased if less than the target fraction of the moves ended feasible for it, or else decreased. When no
+  /// move improves an infeasible solution the penalties of its violations are doubled. Ends with a descent that
+  /// increases the penalties until the solution is feasible. Returns the best feasible solution found.
+  fn oscillation_search(&self, sol: &Solution, problem: &ProblemInstance) -> Option<Solution> {
+    let mut penalties = Penalties {
+      capacity: self.config.oscillation_capacity_penalty,
+      time: self.config.oscillation_time_penalty,
+    };
+    let adjust = |penalty: f64, feasible_moves: usize, moves: usize| {
+      if (feasible_moves as f64) < self.config.oscillation_target_feasible * moves as f64 {
+        penalty * 1.2
+      } else {
+        penalty * 0.85
+      }
+    };
+    let capacity_feasible = |sol: &Solution| sol.routes.iter().all(|route| route.capacity_excess <= 0.0);
+    let time_feasible = |sol: &Solution| sol.routes.iter().all(|route| route.time_excess <= 0 as Time);
+
+    let mut current = sol.clone();
+    let mut best: Option<Solution> = None;
+    let mut moves = 0;
+    let mut capacity_feasible_moves = 0;
+    let mut time_feasible_moves = 0;
+
+    for _ in 0..self.config.oscillation_iters {
+      match self.penalized_move(&current, problem, &penalties) {
+        Some(new_sol) => current = new_sol,
+        None if capacity_feasible(&current) && time_feasible(&current) => break,
+        None => {
+          if !capacity_feasible(&current) {
+            penalties.capacity *= 2.0;
+          }
+          if !time_feasible(&current) {
+            penalties.time *= 2.0;
+          }
+          continue
+        },
+      }
+
+      moves += 1;
+      if capacity_feasible(&current) {
+        capacity_feasible_moves += 1;
+      }
+      if time_feasible(&current) {
+        time_feasible_moves += 1;
+      }
+
+      if capacity_feasible(&current) && time_feasible(&current) && best.as_ref().is_none_or(|best| current.is_better_than(best)) {
+        best = Some(current.clone());
+      }
+
+      if moves == self.config.oscillation_update_iters {
+        penalties.capacity = adjust(penalties.capacity, capacity_feasible_moves, moves);
+        penalties.time = adjust(penalties.time, time_feasible_moves, moves);
+        moves = 0;
+        capacity_feasible_moves = 0;
+        time_feasible_moves = 0;
+      }
+    }
+
+    /* Feasibility restoring descent */
+    let mut restore_iters = self.config.oscillation_iters;
+    while !(capacity_feasible(&current) && time_feasible(&current)) && restore_iters > 0 {
+      restore_iters -= 1;
+
+      match self.penalized_move(&current, problem, &penalties) {
+        Some(new_sol) => current = new_sol,
+        None => {
+          if !capacity_feasible(&current) {
+            penalties.capacity *= 10.0;
+          }
+          if !time_feasible(&current) {
+            penalties.time *= 10.0;
+          }
+        },
+      }
+    }
+
+    if capacity_feasible(&current) && time_feasible(&current) && best.as_ref().is_none_or(|best| current.is_better_than(best)) {
+      best = Some(current);
+    }
+
+    best.and_then(|best| self.strict_solution(&best, problem))
+  }
+
+  ///
+  /// Best move of the relaxed opt2 or insertion searches, whatever is enabled.
+  fn penalized_move(&self, sol: &Solution, problem: &ProblemInstance, penalties: &Penalties) -> Option<Solution> {
+    let criteria = MoveCriteria { penalties: Some(penalties), ..Default::default() };
+    let opt2_sol = if self.config.opt2_search_enabled {
+      self.opt2_local_search(sol, problem, &criteria, false)
+    } else {
+      None
+    };
+
+    opt2_sol.or_else(|| {
+      if self.config.insertion_search_enabled {
+        self.insertion_local_search(sol, problem, &criteria, false)
+      } else {
+        None
+      }
+    })
+  }
+
+  ///
+  /// Rebuilds the relaxed routes of a solution without violations, None if some route is not feasible.
+  fn strict_solution(&self, sol: &Solution, problem: &ProblemInstance) -> Option<Solution> {
+    let mut new_sol = sol.clone();
+
+    for route in new_sol.routes.iter_mut() {
+      *route = problem.build_route(route.vehicle_id, &route.visits())?;
+    }
+    self.evaluate_sol(problem, &mut new_sol);
+
+    Some(new_sol)
+  }
+
+  ///
+  /// Applies the local searches enabled until none improves the solution or local_search_iters is reached.
+  fn descent(&self, sol: Solution, problem: &ProblemInstance) -> Result<Solution, String> {
     let mut best_sol = sol;
     let mut iteration = self.config.local_search_iters;
     let mut should_break = false;
@@ -96,7 +477,7 @@ impl Grasp {
       iteration -= 1;
 
       if self.config.opt2_search_enabled {
-        if let Some(new_sol) = self.opt2_local_search(&best_sol, problem) {
+        if let Some(new_sol) = self.opt2_local_search(&best_sol, problem, &MoveCriteria::default(), false) {
           best_sol = new_sol;
         } else {
           should_break = true;
@@ -104,7 +485,23 @@ impl Grasp {
       }
 
       if self.config.insertion_search_enabled {
-        if let Some(new_sol) = self.insertion_local_search(&best_sol, problem) {
+        if let Some(new_sol) = self.insertion_local_search(&best_sol, problem, &MoveCriteria::default(), false) {
+          best_sol = new_sol
+        } else {
+          should_break = true;
+        }
+      }
+
+      /* Clients left unserved by the repair are inserted even if the prize search is disabled */
+      let required_unserved = best_sol.unserved_clients.iter().any(|client_id| problem.clients[*client_id].prize.is_none());
+
+      if (self.config.prize_search_enabled && problem.has_optional_clients()) || required_unserved {
+        /* Each swap serves a higher priority client, the priorities served prevail over the cost */
+        while let Some(new_sol) = self.priority_local_search(&best_sol, problem) {
+          best_sol = new_sol;
+        }
+
+        if let Some(new_sol) = self.prize_local_search(&best_sol, problem) {
           best_sol = new_sol
         } else {
           should_break = true;
@@ -120,11 +517,61 @@ impl Grasp {
   }
 
   fn build_solution(&self, problem: &ProblemInstance) -> Result<Solution, String> {
+    let (result, name) = match self.config.construction {
+      GraspConstruction::Sequential => self.sequential_construction(problem),
+      GraspConstruction::I1Insertion => (i1_construction(problem, &self.config), "i1".to_string()),
+      GraspConstruction::Savings => (savings_construction(problem, &self.config), "savings".to_string()),
+      GraspConstruction::Regret => (regret_construction(problem, &self.config), "regret".to_string()),
+      GraspConstruction::Sweep => (sweep_construction(problem), "sweep".to_string()),
+    };
+
+    self.construction_solution(result, name, problem)
+  }
+
+  ///
+  /// Creates the solution of the routes built. When the construction left unrouted clients that must be served,
+  /// the solution is repaired and the clients it can't place are left unserved with the repair_penalty,
+  /// except partially served ones.
+  pub fn construction_solution(&self, mut result: ConstructionResult, name: String, problem: &ProblemInstance) -> Result<Solution, String> {
+    let repaired = !result.required_clients(problem).is_empty();
+
+    if repaired && self.config.repair_enabled {
+      repair(problem, &mut result, self.config.repair_ejection_depth, self.config.repair_ejection_neighbors);
+    }
+
+    let feasible = result.required_clients(problem).iter().all(|client_id| {
+      self.config.repair_enabled && result.demands_left[*client_id] == problem.clients[*client_id].demand
+    });
+    if !feasible {
+      return Err("Couldn't find a feasible solution".to_string())
+    }
+
+    let mut sol = Solution {
+      weight_config_name: name,
+      routes: result.routes,
+      unserved_clients: result.unrouted.into_iter().collect(),
+      repaired,
+      ..Default::default()
+    };
+    sol.unserved_clients.sort_unstable();
+
+    self.evaluate_sol(problem, &mut sol);
+    sol.construction_value = sol.value;
+
+    Ok(sol)
+  }
+
+  ///
+  /// Grows the routes of every vehicle at the same time, appending a client chosen from a restricted
+  /// candidate list of the moves of every vehicle.
+  fn sequential_construction(&self, problem: &ProblemInstance) -> (ConstructionResult, String) {
     let mut vehicle_routes = Self::build_grasp_routes(problem);
     let mut all_clients: HashSet<usize> = (0..problem.clients.len())
       .filter(|index| *index != problem.source)
       .map(|index| index.to_owned())
       .collect();
+    /* Demand of each client not delivered yet */
+    let mut demands_left: Vec<Load> = problem.clients.iter().map(|client| client.demand.clone()).collect();
 
     let weight_config: GraspWeightConfig = weighted_choose(
       &self.config.weight_configs,
@@ -132,74 +579,90 @@ impl Grasp {
     ).unwrap().clone();
 
     while !all_clients.is_empty() {
-      let mut moves = self.get_possible_moves(&vehicle_routes, &all_clients, &problem, &weight_config);
+      let mut moves = self.get_possible_moves(&vehicle_routes, &all_clients, &demands_left, problem, &weight_config);
+
+      problem.highest_ranked(&mut moves, |m| m.target_client_id);
 
       moves.sort_by(|m1, m2| m1.cost.partial_cmp(&m2.cost).unwrap());
 
-      let next_move;
-      match self.rcl_choose(&moves) {
-        Some(value) => next_move = value,
-        None => return Err("Couldn't find a feasible solution".to_string()),
+      let next_move = match self.rcl_choose(&moves) {
+        Some(value) => value,
+        None => break,
       };
 
-      all_clients.remove(&next_move.target_client_id);
+      demands_left[next_move.target_client_id].sub(&next_move.delivered);
+      if demands_left[next_move.target_client_id].is_zero() {
+        all_clients.remove(&next_move.target_client_id);
+      }
+
+      if let Some(vroute) = vehicle_routes.get_mut(&next_move.vehicle_id) {
+        let opens_route = vroute.route.len() < 2;
 
-      match vehicle_routes.get_mut(&next_move.vehicle_id) {
-        Some(vroute) => {
-          vroute.update(next_move.target_client_id , problem);
-        },
-        None => (),
-      };
+        if next_move.reload {
+          vroute.update(problem.source, problem);
+        }
+        vroute.visit(next_move.target_client_id, &next_move.delivered, problem);
+
+        /* Unlimited vehicle definitions always keep an idle vehicle */
+        if let Some(vehicle_id) = problem.next_vehicle(next_move.vehicle_id).filter(|_| opens_route) {
+          vehicle_routes.insert(vehicle_id, Self::grasp_route(vehicle_id, problem));
+        }
+      }
     }
 
-    let mut sol: Solution = Solution { weight_config_name: weight_config.display_name, ..Default::default() };
+    let mut result = ConstructionResult {
+      routes: vec![],
+      unrouted: all_clients,
+      demands_left,
+    };
+
+    let mut vehicle_ids: Vec<usize> = vehicle_routes.keys().cloned().collect();
+    vehicle_ids.sort_unstable();
 
-    for vehicle in problem.vehicles.iter() {
-      let vroute = vehicle_routes.get_mut(&vehicle.id).unwrap();
+    for vehicle_id in vehicle_ids {
+      let vroute = vehicle_routes.get_mut(&vehicle_id).unwrap();
 
       /* problem.source is always added to the route */
       if vroute.route.len() < 2 {
         continue
       }
 
-      vroute.update(problem.source, &problem);
+      vroute.update(problem.source, problem);
 
-      sol.routes.push(RouteEntry {
+      let mut route = RouteEntry {
         vehicle_id: vroute.vehicle_id,
         clients: vroute.route.clone(),
-        route_fixed_cost: vehicle.fixed_cost,
-        route_time: vroute.route_time,
-        route_variable_cost: vroute.route_time as f64 * vehicle.variable_cost,
-        demand: vehicle.capacity - vroute.capacity_left,
-      });
-    }
+        ..Default::default()
+      };
+      problem.compute_route_costs(&mut route);
 
-    problem.evaluate_sol(&mut sol);
-    sol.construction_value = sol.value.clone();
+      result.routes.push(route);
+    }
 
-    Ok(sol)
+    (result, weight_config.display_name)
   }
 
   fn build_grasp_routes(problem: &ProblemInstance) -> HashMap<usize, GraspRoute> {
-    problem.vehicles.iter().map(|vehicle| {
-      let mut grasp_route = GraspRoute {
-        vehicle_id: vehicle.id,
-        capacity_left: vehicle.capacity,
-        current_time: problem.clients[problem.source].earliest,
-        current_client_id: problem.source,
-        ..Default::default()
-      };
+    problem.vehicles.iter().map(|vehicle| (vehicle.id, Self::grasp_route(vehicle.id, problem))).collect()
+  }
+
+  fn grasp_route(vehicle_id: usize, problem: &ProblemInstance) -> GraspRoute {
+    let mut grasp_route = GraspRoute {
+      vehicle_id,
+      current_client_id: problem.source,
+      ..Default::default()
+    };
 
-      grasp_route.update(problem.source, problem);
+    grasp_route.update(problem.source, problem);
 
-      (vehicle.id, grasp_route)
-    }).collect()
+    grasp_route
   }
 
   fn get_possible_moves(
     &self,
     vehicle_routes: &HashMap<usize, GraspRoute>,
     available_clients: &HashSet<usize>,
+    demands_left: &[Load],
     problem: &ProblemInstance,
     weights: &GraspWeightConfig,
   ) -> Vec<GraspRouteMove> {
@@ -209,26 +672,46 @@ impl Grasp {
       /* Generate list of possible moves for each vehicle */
       let mut move_list = vec![];
       for client_id in available_clients {
+        if !problem.can_serve(vroute.vehicle_id, *client_id) {
+          continue
+        }
+
         let client = &problem.clients[*client_id];
-        let enough_capacity = client.demand <= vroute.capacity_left;
-        let mut arrival_time = vroute.current_time + problem.distances[vroute.current_client_id][client.id];
+        let mut from = vroute.current_route_client().clone();
+        let mut reload = false;
+
+        /* Clients exceeding the vehicle capacity are served by several full load visits */
+        let delivered = problem.split_delivery(vroute.vehicle_id, &demands_left[*client_id]);
 
-        /* If current_time + distance is less than client.earliest, the vehicle can wait */
-        let wait_time = time_max(client.earliest - arrival_time, 0 as Time);
+        /* If the client doesn't fit, the vehicle may go back to the source to reload */
+        if !vroute.can_load(problem, *client_id, &delivered, false) {
+          let can_reload = vroute.can_reload(problem)
+            && vroute.can_load(problem, *client_id, &delivered, true)
+            && problem.is_move_feasible(vroute.vehicle_id, &from, problem.source);
+
+          if !can_reload {
+            continue
+          }
+
+          from = problem.create_route_entry_client(vroute.vehicle_id, &from, problem.source);
+          reload = true;
+        }
+
+        /* If the vehicle arrives before client.earliest, it can wait */
+        let route_client = problem.create_route_entry_client(vroute.vehicle_id, &from, client.id);
+        let wait_time = route_client.wait_time;
 
         if wait_time > self.config.max_wait_time {
           continue
         }
 
-        arrival_time = time_max(arrival_time, client.earliest);
+        let arrival_time = route_client.arrive_time;
 
-        let enough_time = problem.is_move_feasible(
-          vroute.current_client_id,
-          client.id,
-          vroute.current_time,
-        );
+        /* The vehicle must still get back to the source in time after serving the client */
+        let enough_time = problem.is_move_feasible(vroute.vehicle_id, &from, client.id)
+          && problem.is_move_feasible(vroute.vehicle_id, &route_client, problem.source);
 
-        if !(enough_capacity && enough_time) {
+        if !enough_time {
           continue
         }
 
@@ -236,19 +719,20 @@ impl Grasp {
           problem,
           weights,
           vroute,
+          from.client_id,
           *client_id,
           arrival_time,
           wait_time
         );
-        move_list.push(BasicMove(*client_id, move_cost));
+        move_list.push(BasicMove(*client_id, move_cost, reload, delivered));
       }
 
       /* Sort moves by cost and select the ones no worse than
        * c_min + (c_max - c_min) * moves_per_vehicle_alpha
        */
-      move_list.sort_by(|BasicMove(_, c1), BasicMove(_, c2)| c1.partial_cmp(c2).unwrap());
+      move_list.sort_by(|BasicMove(_, c1, _, _), BasicMove(_, c2, _, _)| c1.partial_cmp(c2).unwrap());
 
-      let move_costs: Vec<f64> = move_list.iter().map(|BasicMove(_, c)| *c).collect();
+      let move_costs: Vec<f64> = move_list.iter().map(|BasicMove(_, c, _, _)| *c).collect();
       let moves_per_vehicle = {
         let alpha_moves = alpha_max_index(
           &move_costs, self.config.moves_per_vehicle_alpha,
@@ -261,12 +745,13 @@ impl Grasp {
         debug!("vehicle={} moves_per_vehicle={}", vroute.vehicle_id, moves_per_vehicle);
       }
 
-      for index in 0..moves_per_vehicle {
-        let BasicMove(client_id, cost) = move_list[index];
+      for BasicMove(client_id, cost, reload, delivered) in move_list.into_iter().take(moves_per_vehicle) {
         ret.push(GraspRouteMove {
-          cost: cost,
+          cost,
           target_client_id: client_id,
           vehicle_id: vroute.vehicle_id,
+          reload,
+          delivered,
         })
       }
     }
@@ -275,39 +760,90 @@ impl Grasp {
   }
 
    ///
-   /// Computes the cost of the move: from vroute.current_client to client_to considering arrival time
+   /// Computes the cost of the move: from vroute.current_client to client_to considering arrival time,
+   /// going through client_from when it's not the current client (to reload at the source).
    /// Assumes to client_to satisfies the restrictions of being eligible.
+  #[allow(clippy::too_many_arguments)]
   fn compute_move_cost(
     &self,
     problem: &ProblemInstance,
     weights: &GraspWeightConfig, 
     vroute: &GraspRoute,
+    client_from: usize,
     client_to: usize,
     arrival_time: Time,
     wait_time: Time
   ) -> f64 {
-    let vehicle = &problem.vehicles[vroute.vehicle_id];
+    let vehicle = problem.vehicle(vroute.vehicle_id);
+    let objective = &problem.objective;
     let fixed_cost = if problem.source == vroute.current_client_id {
-                      20.0 * vehicle.fixed_cost
+                      20.0 * (objective.fixed_cost * vehicle.fixed_cost + objective.routes)
                     } else {
                       0 as Cost
                     };
 
-    let distance = problem.distances[vroute.current_client_id][client_to];
+    let mut distance = problem.distances[client_from][client_to];
+    if client_from != vroute.current_client_id {
+      distance += problem.distances[vroute.current_client_id][client_from];
+    }
     let client = &problem.clients[client_to];
     let close_proximity_time: Time = time_max(client.latest - arrival_time, 0 as Time);
     let overtime = time_max(arrival_time - client.latest, 0 as Time);
+    let early_time = time_max(client.earliest - arrival_time, 0 as Time);
 
     fixed_cost
-    + weights.distance_weight * distance * vehicle.variable_cost as f64
-    + weights.time_weight * close_proximity_time as f64
-    + weights.wait_time_weight * wait_time as f64
-    + problem.deviation_penalty * overtime as f64
+    + weights.distance_weight * distance * (objective.variable_cost * vehicle.variable_cost + objective.distance)
+    + weights.time_weight * close_proximity_time
+    + weights.wait_time_weight * wait_time
+    + problem.deviation_penalty * overtime as f64 * (1 + client.priority) as f64
+    + objective.waiting * vehicle.wait_cost * wait_time
+    + objective.early * problem.early_deviation_penalty * early_time
   }
 
-  fn rcl_choose<'a>(&self, moves: &'a Vec<GraspRouteMove>) -> Option<&'a GraspRouteMove> {
+  fn rcl_choose<'a>(&self, moves: &'a [GraspRouteMove]) -> Option<&'a GraspRouteMove> {
     let costs: Vec<f64> = moves.iter().map(|m| m.cost).collect();
 
     alpha_rcl_choose(moves, &costs, self.config.rcl_alpha, self.config.rcl_min_size)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use crate::types::test_instances::instance;
+  use super::*;
+
+  #[test]
+  fn sequential_construction_serves_mandatory_clients_first() {
+    let problem = instance(json!({ "clients": [{}, { "prize": 100, "priority": 1 }] }), &[0.0, 1.0, 5.0]);
+    let grasp = Grasp { config: Default::default() };
+
+    let (result, _) = grasp.sequential_construction(&problem);
+
+    assert!(result.required_clients(&problem).is_empty());
+    assert_eq!(result.unrouted, HashSet::from([1]));
+    assert_eq!(result.routes[0].visits().iter().map(|visit| visit.client_id).collect::<Vec<_>>(), vec![0, 2, 0]);
+  }
+
+  #[test]
+  fn split_deliveries_sum_the_client_demand() {
+    let problem = instance(json!({
+      "split_deliveries": true,
+      "vehicle_definitions": [{ "count": 3 }],
+      "clients": [{}, { "demand": 25 }],
+    }), &[0.0, 1.0]);
+    let grasp = Grasp { config: Default::default() };
+
+    let sol = grasp.build_solution(&problem).unwrap();
+    let deliveries: Vec<f64> = sol.routes.iter()
+      .flat_map(|route| route.visits())
+      .filter(|visit| visit.client_id == 1)
+      .map(|visit| visit.delivered.0[0])
+      .collect();
+
+    assert!(sol.unserved_clients.is_empty());
+    assert!(deliveries.iter().all(|delivered| *delivered <= 10.0));
+    assert_eq!(deliveries.iter().sum::<f64>(), 25.0);
+  }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+
+use rand::seq::SliceRandom;
+
+use crate::types::{Load, ProblemInstance, RouteEntry, RouteEntryClient};
+
+use super::types::{ConstructionResult, GraspConfig, I1Config, I1Seed};
+use super::utils::alpha_rcl_choose;
+
+///
+/// Insertion of an unrouted client on the route being built.
+struct I1Insertion {
+  client_id: usize,
+  delivered: Load,
+  route: RouteEntry,
+  /* c1 when looking for the position, c2 when choosing the client */
+  cost: f64,
+}
+
+///
+/// Builds the routes one at a time with Solomon's I1 insertion heuristic. Each route starts with a
+/// seed client and the rest are inserted on the position that minimizes c1, the client inserted is
+/// chosen from a restricted candidate list of the ones with greater c2.
+pub fn i1_construction(problem: &ProblemInstance, config: &GraspConfig) -> ConstructionResult {
+  let mut result = ConstructionResult::new(problem);
+  let mut used_vehicles: HashSet<usize> = HashSet::new();
+
+  while let Some(seed) = choose_seed(problem, config, &used_vehicles, &result) {
+    let mut route = seed.route;
+    used_vehicles.insert(route.vehicle_id);
+    result.deliver(seed.client_id, &seed.delivered);
+
+    loop {
+      let mut insertions: Vec<I1Insertion> = result.unrouted.iter()
+        .filter(|client_id| problem.can_serve(route.vehicle_id, **client_id))
+        .filter_map(|client_id| {
+          let delivered = problem.split_delivery(route.vehicle_id, &result.demands_left[*client_id]);
+          let (new_route, c1) = best_insertion(problem, &config.i1_config, &route, *client_id, &delivered)?;
+          let c2 = config.i1_config.lambda * problem.distances[problem.source][*client_id] - c1;
+
+          Some(I1Insertion { client_id: *client_id, delivered, route: new_route, cost: -c2 })
+        })
+        .collect();
+
+      problem.highest_ranked(&mut insertions, |i| i.client_id);
+
+      insertions.sort_by(|i1, i2| i1.cost.partial_cmp(&i2.cost).unwrap());
+      let costs: Vec<f64> = insertions.iter().map(|i| i.cost).collect();
+
+      match alpha_rcl_choose(&insertions, &costs, config.rcl_alpha, config.rcl_min_size) {
+        Some(insertion) => {
+          result.deliver(insertion.client_id, &insertion.delivered);
+          route = insertion.route.clone();
+        },
+        None => break,
+      }
+    }
+
+    result.routes.push(route);
+  }
+
+  result
+}
+
+///
+/// Chooses the seed of a new route from a restricted candidate list sorted by the seed criteria.
+/// The vehicle is the one that can serve it alone whose type has more idle vehicles left,
+/// scarce vehicle types are kept for the clients only they can serve.
+fn choose_seed(
+  problem: &ProblemInstance,
+  config: &GraspConfig,
+  used_vehicles: &HashSet<usize>,
+  result: &ConstructionResult,
+) -> Option<I1Insertion> {
+  let idle_count = problem.idle_count(used_vehicles);
+  let idle_vehicles = problem.idle_vehicle_per_type(used_vehicles);
+
+  let mut candidates: Vec<(usize, f64)> = result.unrouted.iter()
+    .filter(|client_id| {
+      idle_vehicles.iter().any(|vehicle_id| seed_route(problem, *vehicle_id, **client_id, result).is_some())
+    })
+    .map(|client_id| {
+      let client = &problem.clients[*client_id];
+      let cost = match config.i1_config.seed {
+        I1Seed::Farthest => -problem.distances[problem.source][*client_id],
+        I1Seed::EarliestDeadline => client.latest,
+      };
+
+      (*client_id, cost)
+    })
+    .collect();
+
+  problem.highest_ranked(&mut candidates, |(client_id, _)| *client_id);
+
+  candidates.sort_by(|(_, c1), (_, c2)| c1.partial_cmp(c2).unwrap());
+  let costs: Vec<f64> = candidates.iter().map(|(_, cost)| *cost).collect();
+  let (client_id, _) = alpha_rcl_choose(&candidates, &costs, config.rcl_alpha, config.rcl_min_size)?;
+
+  let mut seeds: Vec<I1Insertion> = idle_vehicles.iter()
+    .filter_map(|vehicle_id| seed_route(problem, *vehicle_id, *client_id, result))
+    .collect();
+
+  seeds.shuffle(&mut rand::thread_rng());
+  seeds.into_iter().max_by_key(|seed| idle_count[problem.vehicle(seed.route.vehicle_id).vehicle_type])
+}
+
+///
+/// Route of vehicle_id serving only client_id, if feasible.
+fn seed_route(problem: &ProblemInstance, vehicle_id: usize, client_id: usize, result: &ConstructionResult) -> Option<I1Insertion> {
+  if !problem.can_serve(vehicle_id, client_id) {
+    return None
+  }
+
+  let delivered = problem.split_delivery(vehicle_id, &result.demands_left[client_id]);
+  let visit = RouteEntryClient { delivered: delivered.clone(), ..problem.visit(client_id) };
+  let route = problem.build_route(vehicle_id, &[problem.visit(problem.source), visit, problem.visit(problem.source)])?;
+
+  Some(I1Insertion { client_id, delivered, route, cost: 0.0 })
+}
+
+///
+/// Inserts client_id on the position of the route with minimum c1, it may start a new trip at the end
+/// of the route when the vehicle can reload. Returns the new route and its c1.
+fn best_insertion(
+  problem: &ProblemInstance,
+  i1_config: &I1Config,
+  route: &RouteEntry,
+  client_id: usize,
+  delivered: &Load,
+) -> Option<(RouteEntry, f64)> {
+  let visits = route.visits();
+  let visit = RouteEntryClient { delivered: delivered.clone(), ..problem.visit(client_id) };
+  let mut best: Option<(RouteEntry, f64)> = None;
+
+  let mut segments: Vec<(usize, Vec<RouteEntryClient>)> = (1..visits.len())
+    .map(|position| (position, vec![visit.clone()]))
+    .collect();
+  if problem.vehicle(route.vehicle_id).max_trips > 1 {
+    segments.push((visits.len() - 1, vec![problem.visit(problem.source), visit.clone()]));
+  }
+
+  for (position, segment) in segments {
+    let mut new_visits = visits.clone();
+    new_visits.splice(position..position, segment.iter().cloned());
+
+    let new_route = match problem.build_route(route.vehicle_id, &new_visits) {
+      Some(new_route) => new_route,
+      None => continue,
+    };
+
+    let client_i = visits[position - 1].client_id;
+    let client_j = visits[position].client_id;
+    let mut path = vec![client_i];
+    path.extend(segment.iter().map(|visit| visit.client_id));
+    path.push(client_j);
+
+    let c11 = path.windows(2).map(|arc| problem.distances[arc[0]][arc[1]]).sum::<f64>()
+      - i1_config.mu * problem.distances[client_i][client_j];
+    /* Push forward of the service start of the client after the insertion */
+    let new_route_visits = new_route.visits();
+    let c12 = new_route_visits[position + segment.len()].arrive_time - visits[position].arrive_time;
+    let c1 = i1_config.alpha * c11 + (1.0 - i1_config.alpha) * c12;
+
+    if best.as_ref().is_none_or(|(_, best_c1)| c1 < *best_c1) {
+      best = Some((new_route, c1));
+    }
+  }
+
+  best
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+
+  use serde_json::json;
+
+  use crate::types::test_instances::instance;
+  use super::*;
+
+  #[test]
+  fn i1_construction_serves_mandatory_clients_before_higher_priorities() {
+    let problem = instance(json!({ "clients": [{}, { "prize": 100, "priority": 1 }] }), &[0.0, 1.0, 5.0]);
+
+    let result = i1_construction(&problem, &Default::default());
+
+    assert!(result.required_clients(&problem).is_empty());
+    assert_eq!(result.unrouted, HashSet::from([1]));
+  }
+}
@@ -1,93 +1,83 @@
-use std::collections::HashSet;
+use std::ops::Range;
 
 use crate::types::{RouteEntry, RouteEntryClient, ProblemInstance};
 
 use super::local_search::{LocalSearch, LocalSearchNotFound};
+use super::types::MoveCriteria;
 
 struct InsertionError;
 
-/// Try to insert the client_to_insert slice after insert_after on route1
+/// Try to insert the route2 clients on the moved range after insert_after on route1
 fn try_insert_nodes(
   problem: &ProblemInstance,
   route1: &RouteEntry,
   route2: &RouteEntry,
   insert_after: usize,
-  clients_to_insert: &[RouteEntryClient]
+  moved: Range<usize>,
 ) -> Result<(RouteEntry, RouteEntry), InsertionError> {
 
   let mut new_route1 = route1.clone();
   let mut new_route2 = route2.clone();
-  let mut new_route1_demand = 0.0;
-  let route1_vehicle = &problem.vehicles[new_route1.vehicle_id];
+  let route1_vehicle = problem.vehicle(new_route1.vehicle_id);
+
+  /* Check the load of each trip of the new route before scheduling it */
+  let new_route1_visits = route1.clients[..=insert_after].iter()
+    .chain(route2.clients[moved.clone()].iter())
+    .chain(route1.clients[insert_after + 1..].iter())
+    .filter(|rc| !rc.is_break);
+  if !problem.is_load_feasible(route1_vehicle.id, new_route1_visits) {
+    return Err(InsertionError)
+  }
 
   new_route1.clients.clear();
   new_route2.clients.clear();
 
   /* Insert existing previous clients to new route */
-  for route_client in &route1.clients[..=insert_after] {
-    new_route1.clients.push(route_client.clone());
-    new_route1_demand += problem.clients[route_client.client_id].demand;
+  new_route1.clients.extend_from_slice(&route1.clients[..insert_after]);
+
+  /* The departure from the source was postponed for the client that followed it */
+  let last_previous = &route1.clients[insert_after];
+  if insert_after == 0 {
+    new_route1.clients.push(problem.route_start(route1_vehicle.id));
+  } else if last_previous.client_id == problem.source {
+    problem.append_route_visit(route1_vehicle.id, &mut new_route1.clients, last_previous);
+  } else {
+    new_route1.clients.push(last_previous.clone());
   }
 
   /* Closure in charge of checking feasibility of insertions */
-  let mut route1_append = |from: &RouteEntryClient, to: &RouteEntryClient| -> Option<RouteEntryClient> {
-    if !problem.is_move_feasible(
-      from.client_id, to.client_id, from.leave_time
-    ) {
-      return None
+  let route1_append = |route_clients: &mut Vec<RouteEntryClient>, to: &RouteEntryClient| -> Result<(), InsertionError> {
+    if !problem.can_serve(route1_vehicle.id, to.client_id) {
+      return Err(InsertionError)
     }
-    let client = &problem.clients[to.client_id];
-    let distance = problem.distances[from.client_id][to.client_id];
 
-    new_route1_demand += client.demand;
-
-    if new_route1_demand > route1_vehicle.capacity {
-      return None
+    if !problem.is_move_feasible(route1_vehicle.id, route_clients.last().unwrap(), to.client_id) {
+      return Err(InsertionError)
     }
 
-    let ret = problem.create_route_entry_client(
-      distance, to.client_id, from.leave_time
-    );
+    problem.append_route_visit(route1_vehicle.id, route_clients, to);
 
-    Some(ret)
+    Ok(())
   };
 
-  let mut current_client = &route1.clients[insert_after];
   /* Insert new clients to new route */
-  for route_client in clients_to_insert {
-    if let Some(new_route_client) = route1_append(current_client, route_client) {
-      new_route1.clients.push(new_route_client);
-      current_client = new_route1.clients.last().unwrap();
-    } else {
-      return Err(InsertionError)
-    }
+  for route_client in &route2.clients[moved.clone()] {
+    route1_append(&mut new_route1.clients, route_client)?;
   }
 
   /* Insert existing posterior clients to new route */
-  for route_client in &route1.clients[insert_after + 1..] {
-    if let Some(new_route_client) = route1_append(current_client, route_client) {
-      new_route1.clients.push(new_route_client);
-      current_client = new_route1.clients.last().unwrap();
-    } else {
-      return Err(InsertionError)
-    }
+  for route_client in route1.clients[insert_after + 1..].iter().filter(|rc| !rc.is_break) {
+    route1_append(&mut new_route1.clients, route_client)?;
   }
 
-  let moved_clients_ids: HashSet<usize> = clients_to_insert.iter().map(|c| c.client_id).collect();
-
-  /* Creates a new route2 from the clients left */
-  let client_from = route2.clients.first().unwrap();
-  let mut client_from_id = client_from.client_id;
-  let mut current_time = client_from.leave_time;
-  for route_client in route2.clients.iter() {
-    if !moved_clients_ids.contains(&route_client.client_id) {
-      let arc_time = problem.distances[client_from_id][route_client.client_id];
-      let new_client = problem.create_route_entry_client(
-        arc_time, route_client.client_id, current_time
-      );
-      current_time = new_client.leave_time;
-      client_from_id = new_client.client_id;
-      new_route2.clients.push(new_client);
+  /* Creates a new route2 from the clients left, the departures from the source may change */
+  new_route2.clients.push(problem.route_start(route2.vehicle_id));
+  for (index, route_client) in route2.clients.iter().enumerate().skip(1) {
+    if !route_client.is_break && !moved.contains(&index) {
+      if !problem.is_move_feasible(route2.vehicle_id, new_route2.clients.last().unwrap(), route_client.client_id) {
+        return Err(InsertionError)
+      }
+      problem.append_route_visit(route2.vehicle_id, &mut new_route2.clients, route_client);
     }
   }
 
@@ -97,12 +87,40 @@ fn try_insert_nodes(
   Ok((new_route1, new_route2))
 }
 
+/// Insert the route2 clients on the moved range after insert_after on route1 like try_insert_nodes, on relaxed routes.
+fn try_insert_relaxed_nodes(
+  problem: &ProblemInstance,
+  route1: &RouteEntry,
+  route2: &RouteEntry,
+  insert_after: usize,
+  moved: Range<usize>,
+) -> Result<(RouteEntry, RouteEntry), InsertionError> {
+  let new_route1_visits: Vec<RouteEntryClient> = route1.clients[..=insert_after].iter()
+    .chain(route2.clients[moved.clone()].iter())
+    .chain(route1.clients[insert_after + 1..].iter())
+    .filter(|rc| !rc.is_break)
+    .cloned()
+    .collect();
+  let new_route2_visits: Vec<RouteEntryClient> = route2.clients.iter()
+    .enumerate()
+    .filter(|(index, rc)| !rc.is_break && !moved.contains(index))
+    .map(|(_, rc)| rc.clone())
+    .collect();
+
+  let new_route1 = problem.build_relaxed_route(route1.vehicle_id, &new_route1_visits).ok_or(InsertionError)?;
+  let new_route2 = problem.build_relaxed_route(route2.vehicle_id, &new_route2_visits).ok_or(InsertionError)?;
+
+  Ok((new_route1, new_route2))
+}
+
 /// Try to move <sequence_length> consecutive clients from route1 to route2.
+/// With penalties on the criteria the routes are relaxed, and may exceed the capacities and time windows.
 pub fn insertion_search(
   problem: &ProblemInstance,
   route1: &RouteEntry,
   route2: &RouteEntry,
   sequence_length: usize,
+  criteria: &MoveCriteria,
   first_improvement: bool,
 ) -> Option<(RouteEntry, RouteEntry)> {  
   let ls = LocalSearch::new(first_improvement);
@@ -114,15 +132,25 @@ pub fn insertion_search(
       return Err(LocalSearchNotFound)
     }
 
-    let clients_to_insert = &route2.clients[index2..index2 + sequence_length];
-    let insert_result = try_insert_nodes(
-      problem, route1, route2, index1, clients_to_insert,
-    );
+    if c1.is_break {
+      return Err(LocalSearchNotFound)
+    }
+
+    let moved = index2..index2 + sequence_length;
+    if route2.clients[moved.clone()].iter().any(|rc| rc.client_id == problem.source || rc.is_break) {
+      return Err(LocalSearchNotFound)
+    }
+
+    let insert_result = match criteria.penalties {
+      Some(_) => try_insert_relaxed_nodes(problem, route1, route2, index1, moved),
+      None => try_insert_nodes(problem, route1, route2, index1, moved),
+    };
+
     if let Ok((new_route1, new_route2)) = insert_result {
-      let new_value = new_route1.route_cost() + new_route2.route_cost();
-      let old_value = route1.route_cost() + route2.route_cost();
+      let new_value = criteria.route_cost(&new_route1) + criteria.route_cost(&new_route2);
+      let old_value = criteria.route_cost(route1) + criteria.route_cost(route2);
 
-      if new_value < old_value {
+      if criteria.accepts((route1, route2), (&new_route1, &new_route2), old_value, new_value) {
         return Ok(((new_route1, new_route2), new_value))
       }
     }
@@ -130,3 +158,32 @@ pub fn insertion_search(
     Err(LocalSearchNotFound)
   })
 }
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use crate::types::test_instances::instance;
+  use super::try_insert_nodes;
+
+  #[test]
+  fn moved_clients_take_the_postponed_departure_along() {
+    let problem = instance(json!({
+      "vehicle_definitions": [{ "count": 2 }],
+      "clients": [{}, { "demand": 1, "earliest": 100, "max_ride_time": 50 }, { "demand": 1 }, { "demand": 1 }],
+    }), &[0.0, 10.0, 20.0, 5.0]);
+    let route = |vehicle_id: usize, clients: Vec<usize>| {
+      let visits: Vec<_> = clients.into_iter().map(|client_id| problem.visit(client_id)).collect();
+      problem.build_route(vehicle_id, &visits).unwrap()
+    };
+    let route1 = route(0, vec![0, 3, 0]);
+    let route2 = route(1, vec![0, 1, 2, 0]);
+    assert_eq!(route2.clients[0].leave_time, 90.0);
+
+    let (new_route1, new_route2) = try_insert_nodes(&problem, &route1, &route2, 0, 1..2).ok().unwrap();
+
+    assert_eq!(new_route1.clients[0].leave_time, 90.0);
+    assert_eq!(new_route2.clients[0].leave_time, 0.0);
+    assert_eq!(new_route2.clients[1].arrive_time, 20.0);
+  }
+}
@@ -8,11 +8,11 @@ pub struct LocalSearchNotFound;
 
 impl LocalSearch {
   pub fn new(first_improvement: bool) -> Self {
-    Self { first_improvement: first_improvement }
+    Self { first_improvement }
   }
 
-  pub fn iterate<T, S, Func: Fn(usize, &T, usize, &T) -> Result<(S, f64), LocalSearchNotFound>>(
-    &self, values1: &Vec<T>, values2: &Vec<T>, search: Func
+  pub fn iterate<T, U, S, Func: Fn(usize, &T, usize, &U) -> Result<(S, f64), LocalSearchNotFound>>(
+    &self, values1: &[T], values2: &[U], search: Func
   ) -> Option<S> {
     let mut ret: Option<S> = None;
     let mut best_value: f64 = 0.0;
@@ -50,4 +50,10 @@ impl LocalSearch {
 
     ret
   }
+
+  pub fn iterate_single<T, S, Func: Fn(usize, &T) -> Result<(S, f64), LocalSearchNotFound>>(
+    &self, values: &[T], search: Func
+  ) -> Option<S> {
+    self.iterate(values, &[()], |index, value, _, _| search(index, value))
+  }
 }
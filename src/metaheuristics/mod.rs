@@ -3,8 +3,20 @@ mod utils;
 mod opt2_search;
 mod local_search;
 mod insertion_search;
+mod prize_search;
+mod i1_construction;
+mod savings_construction;
+mod regret_construction;
+mod sweep_construction;
+mod repair;
+mod acceptance;
+mod tabu_search;
+mod genetic;
+mod set_partitioning;
 mod types;
 
-pub use types::GraspConfig;
+pub use types::{GeneticConfig, GraspConfig, RoutePoolConfig};
 
 pub use grasp::*;
+pub use genetic::*;
+pub use set_partitioning::*;
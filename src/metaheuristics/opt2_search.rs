@@ -1,46 +1,52 @@
-use crate::types::{ProblemInstance, RouteEntry, Time};
+use crate::types::{ProblemInstance, RouteEntry, RouteEntryClient};
 
-use crate::utils::time_max;
 use super::local_search::{LocalSearch, LocalSearchNotFound};
+use super::types::MoveCriteria;
 
 ///
-/// Check if the subroute starting from client_index is feasable considering the new_arrival_time
-/// and available_capacity.
+/// Check if the subroute starting from client_index is feasable when done by vehicle_id
+/// after the prefix clients, considering the new route client of client_index, the vehicle trips
+/// load and skills.
 fn is_subroute_feasible(
   problem:&ProblemInstance,
   route: &RouteEntry,
+  vehicle_id: usize,
   client_index: usize,
-  new_arrival_time: Time,
-  available_capacity: f64,
+  new_route_client: RouteEntryClient,
+  prefix: &[RouteEntryClient],
 ) -> bool {
-  let subroute_demand: f64 = route.clients[client_index..]
-                        .iter()
-                        .map(|rc| problem.clients[rc.client_id].demand)
-                        .sum();
-  let vehicle = &problem.vehicles[route.vehicle_id];
-  if subroute_demand + available_capacity > vehicle.capacity {
+  let subroute = route.clients[client_index..].iter().filter(|rc| !rc.is_break);
+
+  let visits = prefix.iter().filter(|rc| !rc.is_break).chain(subroute.clone());
+  if !problem.is_load_feasible(vehicle_id, visits) {
+    return false;
+  }
+
+  if !subroute.clone().all(|rc| problem.can_serve(vehicle_id, rc.client_id)) {
     return false;
   }
 
-  let mut prev_client_id = route.clients[client_index].client_id;
-  let mut current_time = new_arrival_time + problem.clients[prev_client_id].service_time;
+  let mut prev_route_client = new_route_client;
 
-  for client_route in route.clients[client_index + 1..].iter() {
+  for client_route in subroute.skip(1) {
     let client_id = client_route.client_id;
-    let client = &problem.clients[client_id];
 
-    if !problem.is_move_feasible(prev_client_id, client_id, current_time) {
+    if !problem.is_move_feasible(vehicle_id, &prev_route_client, client_id) {
       return false
     }
 
-    let arc_time = problem.distances[prev_client_id][client_id];
-    current_time = time_max(current_time + arc_time, client.earliest) + client.service_time;
-    prev_client_id = client_id;
+    prev_route_client = problem.create_route_entry_client(vehicle_id, &prev_route_client, client_id);
   }
 
   true
 }
 
+///
+/// Index of the first client after index which is not a break stop.
+fn next_client_index(route: &RouteEntry, index: usize) -> usize {
+  (index + 1..route.clients.len()).find(|next| !route.clients[*next].is_break).unwrap()
+}
+
 fn replace_subroute(
   problem: &ProblemInstance,
   route: &mut RouteEntry,
@@ -48,32 +54,19 @@ fn replace_subroute(
   route_index: usize,
   source_index: usize,
 ) {
-  for _ in route_index..route.clients.len() {
+  route.clients.truncate(route_index);
+
+  /* Breaks taken before leaving the last client depend on where the vehicle goes */
+  while route.clients.last().is_some_and(|rc| rc.is_break) {
     route.clients.pop();
   }
-  
-  let mut prev_client_id;
-  let mut current_time;
 
   if route.clients.is_empty() {
-    prev_client_id = problem.source;
-    current_time = problem.clients[prev_client_id].earliest;
-  } else {
-    let route_client = route.clients.last().unwrap();
-    prev_client_id = route_client.client_id;
-    current_time = route_client.leave_time;
+    route.clients.push(problem.route_start(route.vehicle_id));
   }
 
-  for route_client in source.clients[source_index..].iter() {
-    let client_id = route_client.client_id;
-    let arc_time = problem.distances[prev_client_id][client_id];
-
-    let new_route_client = problem.create_route_entry_client(
-      arc_time, client_id, current_time
-    );
-    current_time = new_route_client.leave_time;
-    prev_client_id = client_id;
-    route.clients.push(new_route_client);
+  for route_client in source.clients[source_index..].iter().filter(|rc| !rc.is_break) {
+    problem.append_route_visit(route.vehicle_id, &mut route.clients, route_client);
   }
 
   problem.compute_route_costs(route);
@@ -98,21 +91,39 @@ fn exchange_subroutes(
 }
 
 ///
-/// Return the demand of a subsequence of clients of a route.
-fn get_subroute_demand(problem: &ProblemInstance, route: &RouteEntry, index: usize) -> f64 {
-  route.clients[..=index]
-    .iter()
-    .map(|rc| problem.clients[rc.client_id].demand)
-    .sum()
+/// Exchanges the subroutes like exchange_subroutes on relaxed routes, returns the new routes if
+/// the criteria accepts them, their cost adding the penalty of their violations.
+fn exchange_relaxed_subroutes(
+  problem: &ProblemInstance,
+  route1: &RouteEntry,
+  route2: &RouteEntry,
+  client1_index: usize,
+  client2_index: usize,
+  criteria: &MoveCriteria,
+) -> Option<((RouteEntry, RouteEntry), f64)> {
+  let (mut new_route1, mut new_route2) = exchange_subroutes(problem, route1, route2, client1_index, client2_index);
+  problem.compute_route_excess(&mut new_route1)?;
+  problem.compute_route_excess(&mut new_route2)?;
+
+  let value = criteria.route_cost(&new_route1) + criteria.route_cost(&new_route2);
+  let old_value = criteria.route_cost(route1) + criteria.route_cost(route2);
+
+  if criteria.accepts((route1, route2), (&new_route1, &new_route2), old_value, value) {
+    Some(((new_route1, new_route2), value))
+  } else {
+    None
+  }
 }
 
 ///
 /// Performs the pseudo 2-OPT local search:
 /// Searches for clients with similar time on each route and exchange the route from that point on.
+/// With penalties on the criteria the routes are relaxed, and may exceed the capacities and time windows.
 pub fn opt2_search(
   problem: &ProblemInstance,
   route1: &RouteEntry,
   route2: &RouteEntry,
+  criteria: &MoveCriteria,
   first_improvement: bool,
 ) -> Option<(RouteEntry, RouteEntry)> {
   let ls = LocalSearch::new(first_improvement);
@@ -124,38 +135,50 @@ pub fn opt2_search(
   ls.iterate(&route1.clients, &route2.clients, |index1, c1, index2, c2| {
     let not_found = Err(LocalSearchNotFound);
 
-    if c1.client_id == problem.source || c2.client_id == problem.source {
+    if c1.client_id == problem.source || c2.client_id == problem.source || c1.is_break || c2.is_break {
       return not_found;
     }
 
-    let next_c1 = &route1.clients[index1 + 1];
-    let next_c2 = &route2.clients[index2 + 1];
+    let next_index1 = next_client_index(route1, index1);
+    let next_index2 = next_client_index(route2, index2);
+
+    if criteria.penalties.is_some() {
+      return exchange_relaxed_subroutes(problem, route1, route2, next_index1, next_index2, criteria)
+        .ok_or(LocalSearchNotFound)
+    }
+
+    let next_c1 = &route1.clients[next_index1];
+    let next_c2 = &route2.clients[next_index2];
 
-    let arrival_new_next_c2 = problem.distances[c2.client_id][next_c1.client_id] + c2.leave_time;
-    let arrival_new_next_c1 = problem.distances[c1.client_id][next_c2.client_id] + c1.leave_time;
+    let new_next_c2 = problem.create_route_entry_client(route1.vehicle_id, c1, next_c2.client_id);
+    let new_next_c1 = problem.create_route_entry_client(route2.vehicle_id, c2, next_c1.client_id);
 
-    let prev_subroute1_demand = get_subroute_demand(problem, route1, index1);
-    let prev_subroute2_demand = get_subroute_demand(problem, route2, index2);
+    let prev_subroute1 = &route1.clients[..=index1];
+    let prev_subroute2 = &route2.clients[..=index2];
 
     let exchange_feasible = {
-      problem.is_move_feasible(c1.client_id, next_c2.client_id, c1.leave_time)
+      problem.is_move_feasible(route1.vehicle_id, c1, next_c2.client_id)
       &&
-      problem.is_move_feasible(c2.client_id, next_c1.client_id, c2.leave_time)
+      problem.is_move_feasible(route2.vehicle_id, c2, next_c1.client_id)
       &&
-      is_subroute_feasible(problem, route1, index1 + 1, arrival_new_next_c2, prev_subroute2_demand)
+      is_subroute_feasible(
+        problem, route1, route2.vehicle_id, next_index1, new_next_c1, prev_subroute2,
+      )
       &&
-      is_subroute_feasible(problem, route2, index2 + 1, arrival_new_next_c1, prev_subroute1_demand)
+      is_subroute_feasible(
+        problem, route2, route1.vehicle_id, next_index2, new_next_c2, prev_subroute1,
+      )
     };
 
     if !exchange_feasible {
       return not_found
     }
 
-    let (new_route1, new_route2) = exchange_subroutes(problem, route1, route2, index1 + 1, index2 + 1);
+    let (new_route1, new_route2) = exchange_subroutes(problem, route1, route2, next_index1, next_index2);
     let value = new_route1.route_cost() + new_route2.route_cost();
     let old_value = route1.route_cost() + route2.route_cost();
 
-    if  value < old_value {
+    if criteria.accepts((route1, route2), (&new_route1, &new_route2), old_value, value) {
       return Ok(((new_route1, new_route2), value))
     }
 
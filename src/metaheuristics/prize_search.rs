@@ -0,0 +1,142 @@
+use crate::types::{Cost, ProblemInstance, RouteEntry};
+
+use super::local_search::{LocalSearch, LocalSearchNotFound};
+
+///
+/// Try to remove an optional client, which is not prioritized, from the route.
+/// Returns the new route and the client removed if the route cost decreases more than the client prize.
+pub fn drop_search(
+  problem: &ProblemInstance,
+  route: &RouteEntry,
+  first_improvement: bool,
+) -> Option<(RouteEntry, usize)> {
+  let ls = LocalSearch::new(first_improvement);
+  let visits = route.visits();
+
+  ls.iterate_single(&visits, |index, visit| {
+    let client_id = visit.client_id;
+    let client = &problem.clients[client_id];
+    let prize = match client.prize {
+      /* Split deliveries are not dropped, other visits serve the client too */
+      Some(prize) if client_id != problem.source && visit.delivered == client.demand && !problem.is_prioritized(client_id) => prize,
+      _ => return Err(LocalSearchNotFound),
+    };
+
+    let mut new_visits = visits.clone();
+    new_visits.remove(index);
+
+    if let Some(new_route) = problem.build_route(route.vehicle_id, &new_visits) {
+      let value = new_route.route_cost() + prize;
+
+      if value < route.route_cost() {
+        return Ok(((new_route, client_id), value))
+      }
+    }
+
+    Err(LocalSearchNotFound)
+  })
+}
+
+///
+/// Try to insert an unserved client on any position of the route, which may be empty.
+/// Returns the new route if the route cost increases less than the penalty of leaving the client unserved.
+pub fn insert_search(
+  problem: &ProblemInstance,
+  route: &RouteEntry,
+  client_id: usize,
+  penalty: Cost,
+  first_improvement: bool,
+) -> Option<RouteEntry> {
+  let ls = LocalSearch::new(first_improvement);
+
+  if !problem.can_serve(route.vehicle_id, client_id) {
+    return None
+  }
+
+  let visits = if route.clients.is_empty() {
+    vec![problem.visit(problem.source), problem.visit(problem.source)]
+  } else {
+    route.visits()
+  };
+  let positions: Vec<usize> = (1..visits.len()).collect();
+
+  ls.iterate_single(&positions, |_index, position| {
+    let mut new_visits = visits.clone();
+    new_visits.insert(*position, problem.visit(client_id));
+
+    if let Some(new_route) = problem.build_route(route.vehicle_id, &new_visits) {
+      let value = new_route.route_cost();
+
+      if value < route.route_cost() + penalty {
+        return Ok((new_route, value))
+      }
+    }
+
+    Err(LocalSearchNotFound)
+  })
+}
+
+///
+/// Try to replace an optional client of the route with lower priority than the unserved client_id,
+/// which is inserted on any position. The priority of the clients served prevails over the cost.
+/// Returns the new route and the client removed.
+pub fn swap_search(
+  problem: &ProblemInstance,
+  route: &RouteEntry,
+  client_id: usize,
+  first_improvement: bool,
+) -> Option<(RouteEntry, usize)> {
+  let ls = LocalSearch::new(first_improvement);
+  let priority = problem.clients[client_id].priority;
+
+  if !problem.can_serve(route.vehicle_id, client_id) {
+    return None
+  }
+
+  let visits = route.visits();
+
+  ls.iterate(&visits, &visits, |index, visit, position, _| {
+    let client = &problem.clients[visit.client_id];
+    let swappable = client.prize.is_some()
+      && client.priority < priority
+      && visit.client_id != problem.source
+      && visit.delivered == client.demand;
+
+    if !swappable || position == 0 {
+      return Err(LocalSearchNotFound)
+    }
+
+    let mut new_visits = visits.clone();
+    new_visits.insert(position, problem.visit(client_id));
+    new_visits.remove(if index < position { index } else { index + 1 });
+
+    match problem.build_route(route.vehicle_id, &new_visits) {
+      Some(new_route) => {
+        let value = new_route.route_cost();
+        Ok(((new_route, visit.client_id), value))
+      },
+      None => Err(LocalSearchNotFound),
+    }
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use crate::types::test_instances::instance;
+  use super::*;
+
+  #[test]
+  fn insert_search_needs_the_prize_to_cover_prioritized_clients() {
+    let problem = instance(json!({
+      "clients": [{}, { "demand": 1, "prize": 5 }, { "demand": 1, "prize": 5, "priority": 1 }],
+    }), &[0.0, 1.0, 10.0]);
+    let route = problem.build_route(0, &[problem.visit(0), problem.visit(1), problem.visit(0)]).unwrap();
+
+    assert!(problem.is_prioritized(2));
+    assert!(!problem.is_prioritized(1));
+    assert!(insert_search(&problem, &route, 2, 5.0, false).is_none());
+    assert!(insert_search(&problem, &route, 2, 50.0, false).is_some());
+  }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashSet;
+
+use crate::types::{ProblemInstance, RouteEntry};
+
+use super::types::{ConstructionResult, GraspConfig};
+use super::utils::{alpha_rcl_choose, cheapest_insertion};
+
+/* New route with the cheapest insertion and its cost, None if it's not feasible */
+type Insertion = Option<(RouteEntry, f64)>;
+
+///
+/// Regret of inserting a client on its cheapest route instead of the next ones.
+struct RegretCandidate {
+  client_id: usize,
+  route_index: usize,
+  /* number of routes the client can be inserted on */
+  options: usize,
+  regret: f64,
+}
+
+///
+/// Builds every route at the same time with the regret-k insertion heuristic. The cheapest insertion of each
+/// unrouted client is computed for every route, including an empty one of each vehicle type with idle vehicles.
+/// The client inserted is chosen from a restricted candidate list of the ones with greater regret, the sum of
+/// the differences between its k - 1 next cheapest routes and the cheapest one. Clients with less than k
+/// routes left are inserted first.
+pub fn regret_construction(problem: &ProblemInstance, config: &GraspConfig) -> ConstructionResult {
+  let mut result = ConstructionResult::new(problem);
+  let mut routes: Vec<RouteEntry> = problem.vehicles.iter()
+    .map(|vehicle| RouteEntry { vehicle_id: vehicle.id, ..Default::default() })
+    .collect();
+  /* Cheapest insertion of each client on each route and its cost, computed when needed */
+  let mut insertions: Vec<Vec<Option<Insertion>>> = vec![vec![None; routes.len()]; problem.clients.len()];
+  let k = config.regret_k.max(2);
+
+  loop {
+    /* Idle vehicles of the same type are interchangeable, only one of them may open a new route */
+    let mut vehicle_types = HashSet::new();
+    let route_indexes: Vec<usize> = (0..routes.len())
+      .filter(|index| !routes[*index].clients.is_empty() || vehicle_types.insert(problem.vehicle(routes[*index].vehicle_id).vehicle_type))
+      .collect();
+
+    let mut candidates: Vec<RegretCandidate> = vec![];
+    for client_id in result.unrouted.iter() {
+      let mut costs: Vec<(f64, usize)> = vec![];
+
+      for route_index in route_indexes.iter() {
+        let insertion = insertions[*client_id][*route_index].get_or_insert_with(|| {
+          cheapest_insertion(problem, &routes[*route_index], *client_id, &result)
+        });
+
+        if let Some((_, cost)) = insertion {
+          costs.push((*cost, *route_index));
+        }
+      }
+
+      if costs.is_empty() {
+        continue
+      }
+
+      costs.sort_by(|(c1, _), (c2, _)| c1.partial_cmp(c2).unwrap());
+      let (min_cost, route_index) = costs[0];
+      let regret = costs.iter().take(k).skip(1).map(|(cost, _)| cost - min_cost).sum();
+
+      candidates.push(RegretCandidate { client_id: *client_id, route_index, options: costs.len(), regret });
+    }
+
+    problem.highest_ranked(&mut candidates, |c| c.client_id);
+
+    let min_options = candidates.iter().map(|c| c.options).min().unwrap_or(k);
+    if min_options < k {
+      candidates.retain(|c| c.options == min_options);
+    }
+
+    candidates.sort_by(|c1, c2| c2.regret.partial_cmp(&c1.regret).unwrap());
+    let costs: Vec<f64> = candidates.iter().map(|c| -c.regret).collect();
+
+    let candidate = match alpha_rcl_choose(&candidates, &costs, config.rcl_alpha, config.rcl_min_size) {
+      Some(candidate) => candidate,
+      None => break,
+    };
+
+    let (new_route, _) = insertions[candidate.client_id][candidate.route_index].take().unwrap().unwrap();
+    let opens_route = routes[candidate.route_index].clients.is_empty();
+    result.deliver_on(candidate.client_id, &new_route);
+
+    /* Unlimited vehicle definitions always keep an idle vehicle */
+    if let Some(vehicle_id) = problem.next_vehicle(new_route.vehicle_id).filter(|_| opens_route) {
+      routes.push(RouteEntry { vehicle_id, ..Default::default() });
+      for client_insertions in insertions.iter_mut() {
+        client_insertions.push(None);
+      }
+    }
+    routes[candidate.route_index] = new_route;
+
+    /* Insertions on the route changed, and of the client if part of its demand is left */
+    for client_insertions in insertions.iter_mut() {
+      client_insertions[candidate.route_index] = None;
+    }
+    for insertion in insertions[candidate.client_id].iter_mut() {
+      *insertion = None;
+    }
+  }
+
+  result.routes = routes.into_iter().filter(|route| !route.clients.is_empty()).collect();
+
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+
+  use serde_json::json;
+
+  use crate::types::test_instances::instance;
+  use super::*;
+
+  #[test]
+  fn regret_construction_serves_mandatory_clients_before_higher_priorities() {
+    let problem = instance(json!({ "clients": [{}, { "prize": 100, "priority": 1 }] }), &[0.0, 1.0, 5.0]);
+
+    let result = regret_construction(&problem, &Default::default());
+
+    assert!(result.required_clients(&problem).is_empty());
+    assert_eq!(result.unrouted, HashSet::from([1]));
+  }
+}
@@ -0,0 +1,141 @@
+use std::cmp::Reverse;
+
+use crate::types::{Load, ProblemInstance, RouteEntry, RouteEntryClient, Time};
+
+use super::types::ConstructionResult;
+use super::utils::cheapest_visit_insertion;
+
+/* Routes after placing a visit, the quantity the visit delivers and the visits of optional clients ejected and left unserved */
+type Placement = (Vec<RouteEntry>, Load, Vec<RouteEntryClient>);
+
+///
+/// Places the clients the construction left unrouted that must be served, higher priority clients first.
+/// Each visit is inserted on the cheapest position of the routes, or on a new route of an unused vehicle,
+/// delivering as much of the demand left as fits the vehicle of the route. When it doesn't fit, one of the
+/// max_neighbors clients closest to it is ejected from its route to make room, and is placed the same way
+/// or left unserved if it's optional, in chains of up to max_depth ejections.
+pub fn repair(problem: &ProblemInstance, result: &mut ConstructionResult, max_depth: usize, max_neighbors: usize) {
+  let mut clients = result.required_clients(problem);
+  clients.sort_unstable_by_key(|client_id| Reverse(problem.service_rank(*client_id)));
+
+  for client_id in clients {
+    while result.unrouted.contains(&client_id) {
+      let visit = RouteEntryClient { delivered: result.demands_left[client_id].clone(), ..problem.visit(client_id) };
+      let mut routes = result.routes.clone();
+      routes.extend(problem.unused_vehicle_routes(&result.routes));
+
+      match place_visit(problem, &routes, &visit, true, max_depth, max_neighbors) {
+        Some((new_routes, delivered, ejected)) => {
+          result.deliver(client_id, &delivered);
+          for ejected_visit in ejected {
+            result.demands_left[ejected_visit.client_id].add(&ejected_visit.delivered);
+            result.unrouted.insert(ejected_visit.client_id);
+          }
+          result.routes = new_routes.into_iter().filter(|route| !route.clients.is_empty()).collect();
+        },
+        None => break,
+      }
+    }
+  }
+}
+
+///
+/// Inserts the visit on the cheapest route, or ejects a client close to it to make room and places the client
+/// ejected with depth - 1 ejections left. Optional clients which are not prioritized are ejected and left unserved.
+/// With split, the visit delivers on each route the part of its quantity that fits the vehicle, ejected visits
+/// are placed whole.
+fn place_visit(
+  problem: &ProblemInstance,
+  routes: &[RouteEntry],
+  visit: &RouteEntryClient,
+  split: bool,
+  depth: usize,
+  max_neighbors: usize,
+) -> Option<Placement> {
+  let route_visit = |route: &RouteEntry| -> RouteEntryClient {
+    if split {
+      RouteEntryClient { delivered: problem.split_delivery(route.vehicle_id, &visit.delivered), ..visit.clone() }
+    } else {
+      visit.clone()
+    }
+  };
+
+  let cheapest = routes.iter()
+    .enumerate()
+    .filter_map(|(index, route)| cheapest_visit_insertion(problem, route, &route_visit(route)).map(|insertion| (index, insertion)))
+    .min_by(|(_, (_, c1)), (_, (_, c2))| c1.partial_cmp(c2).unwrap());
+
+  if let Some((index, (new_route, _))) = cheapest {
+    let delivered = route_visit(&routes[index]).delivered;
+    let mut new_routes = routes.to_vec();
+    new_routes[index] = new_route;
+    return Some((new_routes, delivered, vec![]))
+  }
+
+  if depth == 0 {
+    return None
+  }
+
+  /* Only the clients closest to the visit are ejected, each ejection rebuilds its route */
+  let mut ejections: Vec<(usize, usize, Time)> = vec![];
+  for (index, route) in routes.iter().enumerate() {
+    for (position, ejected) in route.visits().iter().enumerate() {
+      if ejected.client_id != problem.source && ejected.client_id != visit.client_id {
+        ejections.push((index, position, problem.distances[visit.client_id][ejected.client_id]));
+      }
+    }
+  }
+  ejections.sort_by(|(_, _, d1), (_, _, d2)| d1.partial_cmp(d2).unwrap());
+
+  for (index, position, _) in ejections.into_iter().take(max_neighbors) {
+    let route = &routes[index];
+    let mut reduced_visits = route.visits();
+    let ejected = reduced_visits.remove(position);
+
+    let new_visit = route_visit(route);
+    let new_route = problem.build_route(route.vehicle_id, &reduced_visits)
+      .and_then(|reduced_route| cheapest_visit_insertion(problem, &reduced_route, &new_visit));
+    let mut new_routes = routes.to_vec();
+    match new_route {
+      Some((new_route, _)) => new_routes[index] = new_route,
+      None => continue,
+    }
+
+    let client = &problem.clients[ejected.client_id];
+    if client.prize.is_some() && !problem.is_prioritized(ejected.client_id) && ejected.delivered == client.demand {
+      return Some((new_routes, new_visit.delivered, vec![ejected]))
+    }
+
+    if let Some((new_routes, _, left)) = place_visit(problem, &new_routes, &ejected, false, depth - 1, max_neighbors) {
+      return Some((new_routes, new_visit.delivered, left))
+    }
+  }
+
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use crate::types::test_instances::instance;
+  use super::super::types::ConstructionResult;
+  use super::repair;
+
+  #[test]
+  fn repair_splits_the_demand_by_the_vehicle_of_each_route() {
+    let problem = instance(json!({
+      "split_deliveries": true,
+      "vehicle_definitions": [{ "capacity": 5, "fixed_cost": 10 }, { "capacity": 20 }],
+      "clients": [{}, { "demand": 15 }],
+    }), &[0.0, 1.0]);
+    let mut result = ConstructionResult::new(&problem);
+
+    repair(&problem, &mut result, 2, 10);
+
+    let visits: Vec<_> = result.routes.iter().flat_map(|route| route.visits()).filter(|visit| visit.client_id == 1).collect();
+    assert!(result.unrouted.is_empty());
+    assert_eq!(visits.len(), 1);
+    assert_eq!(visits[0].delivered.0[0], 15.0);
+  }
+}
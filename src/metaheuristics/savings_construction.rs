@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+
+use rand::Rng;
+
+use crate::types::{ProblemInstance, RouteEntry, RouteEntryClient};
+
+use super::types::{ConstructionResult, GraspConfig};
+use super::utils::cheapest_insertion;
+
+///
+/// Saving of visiting client_to right after client_from instead of going through the source.
+struct Saving {
+  client_from: usize,
+  client_to: usize,
+  value: f64,
+}
+
+///
+/// Builds the routes with the parallel Clarke-Wright savings heuristic. Every client starts on its own
+/// route and the routes are merged joining the end of one with the start of another, the merge is chosen
+/// at random from the savings_rcl_size greater savings left. Each route is done by the vehicle type that
+/// makes it cheaper, routes are assigned to the vehicles at the end.
+pub fn savings_construction(problem: &ProblemInstance, config: &GraspConfig) -> ConstructionResult {
+  let mut result = ConstructionResult::new(problem);
+  let mut routes: Vec<Option<RouteEntry>> = vec![];
+  /* Route each client starts or ends, if it can be merged there */
+  let mut starts: Vec<Option<usize>> = vec![None; problem.clients.len()];
+  let mut ends: Vec<Option<usize>> = vec![None; problem.clients.len()];
+
+  let mut clients: Vec<usize> = result.unrouted.iter().cloned().collect();
+  clients.sort_unstable();
+
+  for client_id in clients {
+    /* Clients exceeding the vehicle capacity get a route for each full load, only the last one is merged */
+    while !result.demands_left[client_id].is_zero() {
+      let route = cheapest_route(problem, |vehicle_id| {
+        let delivered = problem.split_delivery(vehicle_id, &result.demands_left[client_id]);
+        vec![
+          problem.visit(problem.source),
+          RouteEntryClient { delivered, ..problem.visit(client_id) },
+          problem.visit(problem.source),
+        ]
+      });
+
+      match route {
+        Some(route) => {
+          result.deliver(client_id, &route.clients[1].delivered);
+          starts[client_id] = Some(routes.len());
+          ends[client_id] = Some(routes.len());
+          routes.push(Some(route));
+        },
+        None => break,
+      }
+    }
+  }
+
+  let routed: Vec<usize> = (0..problem.clients.len()).filter(|client_id| starts[*client_id].is_some()).collect();
+  let mut savings: Vec<Saving> = vec![];
+  for &client_from in routed.iter() {
+    for &client_to in routed.iter() {
+      if client_from == client_to {
+        continue
+      }
+
+      let value = problem.distances[client_from][problem.source]
+        + problem.distances[problem.source][client_to]
+        - problem.distances[client_from][client_to];
+      savings.push(Saving { client_from, client_to, value });
+    }
+  }
+  /* The greater savings are at the end */
+  savings.sort_by(|s1, s2| s1.value.partial_cmp(&s2.value).unwrap());
+
+  let mut rng = rand::thread_rng();
+  loop {
+    /* A client stops being an end of a route once merged there, so those savings are discarded */
+    let mut rcl: Vec<Saving> = vec![];
+    while rcl.len() < config.savings_rcl_size.max(1) {
+      match savings.pop() {
+        Some(saving) => {
+          let mergeable = match (ends[saving.client_from], starts[saving.client_to]) {
+            (Some(route1), Some(route2)) => route1 != route2,
+            _ => false,
+          };
+          if mergeable {
+            rcl.push(saving);
+          }
+        },
+        None => break,
+      }
+    }
+
+    if rcl.is_empty() {
+      break
+    }
+
+    let saving = rcl.remove(rng.gen_range(0, rcl.len()));
+    savings.extend(rcl.into_iter().rev());
+
+    let index1 = ends[saving.client_from].unwrap();
+    let index2 = starts[saving.client_to].unwrap();
+    let route1 = routes[index1].as_ref().unwrap();
+    let route2 = routes[index2].as_ref().unwrap();
+    let visits1 = route1.visits();
+    let visits2 = route2.visits();
+
+    let merged = cheapest_route(problem, |_| {
+      visits1[..visits1.len() - 1].iter().chain(visits2[1..].iter()).cloned().collect()
+    });
+
+    if let Some(merged) = merged {
+      if merged.route_cost() < route1.route_cost() + route2.route_cost() {
+        let last_client_id = visits2[visits2.len() - 2].client_id;
+
+        ends[saving.client_from] = None;
+        starts[saving.client_to] = None;
+        ends[last_client_id] = Some(index1);
+        routes[index1] = Some(merged);
+        routes[index2] = None;
+      }
+    }
+  }
+
+  assign_vehicles(problem, routes.into_iter().flatten().collect(), &mut result);
+  insert_unrouted(problem, &mut result);
+
+  result
+}
+
+///
+/// Builds the visits of the vehicle types and returns the cheapest feasible route,
+/// done by the first vehicle of its type.
+fn cheapest_route<F>(problem: &ProblemInstance, visits: F) -> Option<RouteEntry>
+  where F: Fn(usize) -> Vec<RouteEntryClient>
+{
+  (0..problem.vehicle_definitions.len())
+    .filter_map(|vehicle_type| problem.vehicles.iter().find(|vehicle| vehicle.vehicle_type == vehicle_type))
+    .filter_map(|vehicle| problem.build_route(vehicle.id, &visits(vehicle.id)))
+    .min_by(|r1, r2| r1.route_cost().partial_cmp(&r2.route_cost()).unwrap())
+}
+
+///
+/// Assigns a vehicle to each route, of its type if there are vehicles left or else of the cheapest feasible
+/// type left. Routes serving clients that must be served, then higher priority ones, and with greater value
+/// are assigned first, the clients of the routes without vehicles are left unrouted.
+fn assign_vehicles(problem: &ProblemInstance, mut routes: Vec<RouteEntry>, result: &mut ConstructionResult) {
+  let max_rank = |route: &RouteEntry| route.clients.iter()
+    .filter(|rc| rc.client_id != problem.source)
+    .map(|rc| problem.service_rank(rc.client_id))
+    .max();
+  routes.sort_by(|r1, r2| {
+    max_rank(r2).cmp(&max_rank(r1)).then(r2.route_cost().partial_cmp(&r1.route_cost()).unwrap())
+  });
+
+  let mut used: HashSet<usize> = HashSet::new();
+
+  for route in routes {
+    let vehicle_type = problem.vehicle(route.vehicle_id).vehicle_type;
+    let idle = problem.idle_vehicles(&used);
+    let same_type = idle.iter().find(|vehicle_id| problem.vehicle(**vehicle_id).vehicle_type == vehicle_type);
+
+    let assigned = match same_type {
+      Some(vehicle_id) => Some(RouteEntry { vehicle_id: *vehicle_id, ..route.clone() }),
+      None => {
+        let visits = route.visits();
+        let mut vehicle_types = HashSet::new();
+        idle.iter()
+          .filter(|vehicle_id| vehicle_types.insert(problem.vehicle(**vehicle_id).vehicle_type))
+          .filter_map(|vehicle_id| problem.build_route(*vehicle_id, &visits))
+          .min_by(|r1, r2| r1.route_cost().partial_cmp(&r2.route_cost()).unwrap())
+      },
+    };
+
+    match assigned {
+      Some(assigned) => {
+        used.insert(assigned.vehicle_id);
+        result.routes.push(assigned);
+      },
+      None => {
+        for visit in route.visits().iter().filter(|visit| visit.client_id != problem.source) {
+          result.demands_left[visit.client_id].add(&visit.delivered);
+          result.unrouted.insert(visit.client_id);
+        }
+      },
+    }
+  }
+}
+
+///
+/// Inserts the clients left without vehicle on the position of the routes that increases their value the least,
+/// higher priority clients first.
+fn insert_unrouted(problem: &ProblemInstance, result: &mut ConstructionResult) {
+  let mut clients: Vec<usize> = result.unrouted.iter().cloned().collect();
+  clients.sort_unstable_by_key(|client_id| std::cmp::Reverse(problem.service_rank(*client_id)));
+
+  for client_id in clients {
+    let best = result.routes.iter()
+      .enumerate()
+      .filter_map(|(index, route)| cheapest_insertion(problem, route, client_id, result).map(|insertion| (index, insertion)))
+      .min_by(|(_, (_, c1)), (_, (_, c2))| c1.partial_cmp(c2).unwrap());
+
+    if let Some((index, (new_route, _))) = best {
+      result.deliver_on(client_id, &new_route);
+      result.routes[index] = new_route;
+    }
+  }
+}
@@ -0,0 +1,415 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+
+use rand::seq::SliceRandom;
+
+use crate::types::{Cost, ProblemInstance, RouteEntry, Solution, Time};
+
+use super::types::RoutePoolConfig;
+
+/* Vehicle type and clients of a pooled route */
+type PoolKey = (usize, Vec<usize>);
+
+///
+/// Pooled route ordered by its cost per client.
+struct PoolEntry {
+  cost_per_client: Cost,
+  key: PoolKey,
+}
+
+impl PoolEntry {
+  fn new(key: PoolKey, route: &RouteEntry) -> Self {
+    PoolEntry { cost_per_client: route.route_cost() / key.1.len() as Cost, key }
+  }
+}
+
+impl PartialEq for PoolEntry {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for PoolEntry {}
+
+impl PartialOrd for PoolEntry {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for PoolEntry {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self.cost_per_client.total_cmp(&other.cost_per_client).then_with(|| self.key.cmp(&other.key))
+  }
+}
+
+///
+/// Routes found by the iterations, the cheapest one of each vehicle type and set of clients.
+pub struct RoutePool {
+  size: usize,
+  routes: HashMap<PoolKey, RouteEntry>,
+  /* keys of the routes from the lower cost per client */
+  entries: BTreeSet<PoolEntry>,
+}
+
+impl RoutePool {
+  pub fn new(size: usize) -> Self {
+    RoutePool { size, routes: HashMap::new(), entries: BTreeSet::new() }
+  }
+
+  pub fn add_solution(&mut self, problem: &ProblemInstance, sol: &Solution) {
+    for route in sol.routes.iter() {
+      self.add(problem, route);
+    }
+  }
+
+  ///
+  /// Adds the route unless the pool has a cheaper one for the same clients and vehicle type. When the pool is
+  /// full, the route with the greater cost per client is removed. Routes delivering part of the demand of a
+  /// client aren't added, since the set partitioning serves each client with a single route.
+  pub fn add(&mut self, problem: &ProblemInstance, route: &RouteEntry) {
+    let partial_delivery = route.visits().iter()
+      .any(|visit| visit.client_id != problem.source && visit.delivered != problem.clients[visit.client_id].demand);
+
+    if partial_delivery {
+      return
+    }
+
+    let clients = route_clients(problem, route);
+    if clients.is_empty() {
+      return
+    }
+
+    let key = (problem.vehicle(route.vehicle_id).vehicle_type, clients);
+    if self.routes.get(&key).is_some_and(|other| other.route_cost() <= route.route_cost()) {
+      return
+    }
+
+    if let Some(other) = self.routes.insert(key.clone(), route.clone()) {
+      self.entries.remove(&PoolEntry::new(key.clone(), &other));
+    }
+    self.entries.insert(PoolEntry::new(key, route));
+
+    if self.routes.len() > self.size {
+      if let Some(worst) = self.entries.pop_last() {
+        self.routes.remove(&worst.key);
+      }
+    }
+  }
+
+  pub fn routes(&self) -> Vec<RouteEntry> {
+    self.routes.values().cloned().collect()
+  }
+}
+
+struct Column {
+  route: RouteEntry,
+  vehicle_type: usize,
+  clients: Vec<usize>,
+  cost: Cost,
+}
+
+///
+/// Depth first branch and bound of the set partitioning of the free clients with the columns serving only free
+/// clients. Each node serves the first free client left, in the order of fewer columns, with each column that
+/// doesn't serve any client twice and has a vehicle of its type left, from the cheapest, or leaves it unserved
+/// if it's optional. The lower bound of the clients left is the sum of their cheapest cost per client.
+struct BranchAndBound<'a> {
+  problem: &'a ProblemInstance,
+  columns: &'a [Column],
+  lower_bounds: &'a [Cost],
+  /* columns serving each free client, from the cheapest */
+  client_columns: Vec<Vec<usize>>,
+  /* free clients in the order they are branched on */
+  order: Vec<usize>,
+  vehicles_left: Vec<usize>,
+  served: Vec<bool>,
+  chosen: Vec<usize>,
+  best_cost: Cost,
+  best: Option<Vec<usize>>,
+  nodes: usize,
+  max_nodes: usize,
+}
+
+impl<'a> BranchAndBound<'a> {
+  fn search(&mut self, from: usize, cost: Cost, lower_bound: Cost) {
+    self.nodes += 1;
+    if self.nodes > self.max_nodes || cost + lower_bound >= self.best_cost {
+      return
+    }
+
+    let Some(position) = (from..self.order.len()).find(|position| !self.served[self.order[*position]]) else {
+      self.best_cost = cost;
+      self.best = Some(self.chosen.clone());
+      return
+    };
+    let client_id = self.order[position];
+
+    for index in 0..self.client_columns[client_id].len() {
+      let column_index = self.client_columns[client_id][index];
+      let column = &self.columns[column_index];
+
+      if self.vehicles_left[column.vehicle_type] == 0 || column.clients.iter().any(|client_id| self.served[*client_id]) {
+        continue
+      }
+
+      let column_bound: Cost = column.clients.iter().map(|client_id| self.lower_bounds[*client_id]).sum();
+
+      for client_id in column.clients.iter() {
+        self.served[*client_id] = true;
+      }
+      self.vehicles_left[column.vehicle_type] -= 1;
+      self.chosen.push(column_index);
+
+      self.search(position + 1, cost + column.cost, lower_bound - column_bound);
+
+      self.chosen.pop();
+      self.vehicles_left[column.vehicle_type] += 1;
+      for client_id in column.clients.iter() {
+        self.served[*client_id] = false;
+      }
+    }
+
+    if let Some(prize) = self.problem.clients[client_id].prize {
+      self.served[client_id] = true;
+      self.search(position + 1, cost + prize, lower_bound - self.lower_bounds[client_id]);
+      self.served[client_id] = false;
+    }
+  }
+}
+
+///
+/// Columns and state of the large neighborhood search over the set partitioning.
+struct PartitioningSearch<'a> {
+  problem: &'a ProblemInstance,
+  config: &'a RoutePoolConfig,
+  columns: Vec<Column>,
+  client_columns: Vec<Vec<usize>>,
+  lower_bounds: Vec<Cost>,
+  type_vehicles: Vec<Vec<usize>>,
+  /* columns of the current solution */
+  chosen: Vec<usize>,
+}
+
+impl<'a> PartitioningSearch<'a> {
+  ///
+  /// Chosen columns of a random one and the ones with the clients closer to a random client of it.
+  fn related_columns(&self) -> Vec<usize> {
+    let mut rng = rand::thread_rng();
+    let Some(&first) = self.chosen.choose(&mut rng) else { return vec![] };
+    let client_id = *self.columns[first].clients.choose(&mut rng).unwrap();
+    let closeness = |column_index: usize| {
+      self.columns[column_index].clients.iter()
+        .map(|other_id| self.problem.distances[client_id][*other_id])
+        .fold(f64::INFINITY, f64::min)
+    };
+
+    let mut related: Vec<(Time, usize)> = self.chosen.iter().map(|index| (closeness(*index), *index)).collect();
+    related.sort_by(|(c1, _), (c2, _)| c1.partial_cmp(c2).unwrap());
+
+    related.into_iter().take(self.config.lns_routes.max(1)).map(|(_, index)| index).collect()
+  }
+
+  ///
+  /// Frees the clients of some related columns of the current solution and the unserved ones, and partitions
+  /// them again with the branch and bound. Returns whether the solution improved.
+  fn improve(&mut self, served: &mut [bool]) -> bool {
+    let destroyed = self.related_columns();
+    let mut free = vec![false; self.problem.clients.len()];
+    let mut vehicles_left: Vec<usize> = self.type_vehicles.iter().map(|vehicles| vehicles.len()).collect();
+    let mut old_cost = 0 as Cost;
+
+    for index in self.chosen.iter() {
+      let column = &self.columns[*index];
+
+      if destroyed.contains(index) {
+        old_cost += column.cost;
+        column.clients.iter().for_each(|client_id| free[*client_id] = true);
+      } else {
+        vehicles_left[column.vehicle_type] -= 1;
+      }
+    }
+
+    for client_id in (0..self.problem.clients.len()).filter(|client_id| *client_id != self.problem.source && !served[*client_id]) {
+      old_cost += self.problem.clients[client_id].prize.unwrap_or(0 as Cost);
+      free[client_id] = true;
+    }
+
+    let mut order: Vec<usize> = (0..free.len()).filter(|client_id| free[*client_id]).collect();
+    let client_columns: Vec<Vec<usize>> = (0..free.len()).map(|client_id| {
+      if !free[client_id] {
+        return vec![]
+      }
+
+      self.client_columns[client_id].iter()
+        .filter(|index| self.columns[**index].clients.iter().all(|other_id| free[*other_id]))
+        .cloned()
+        .collect()
+    }).collect();
+    order.sort_by_key(|client_id| client_columns[*client_id].len());
+
+    let lower_bound = order.iter().map(|client_id| self.lower_bounds[*client_id]).sum();
+    let mut bnb = BranchAndBound {
+      problem: self.problem,
+      columns: &self.columns,
+      lower_bounds: &self.lower_bounds,
+      client_columns,
+      order,
+      vehicles_left,
+      served: free.iter().map(|free| !free).collect(),
+      chosen: vec![],
+      best_cost: old_cost - 1e-6,
+      best: None,
+      nodes: 0,
+      max_nodes: self.config.max_nodes,
+    };
+    bnb.search(0, 0 as Cost, lower_bound);
+
+    let Some(new_columns) = bnb.best else { return false };
+
+    self.chosen.retain(|index| !destroyed.contains(index));
+    for client_id in (0..free.len()).filter(|client_id| free[*client_id]) {
+      served[client_id] = false;
+    }
+    for index in new_columns {
+      self.columns[index].clients.iter().for_each(|client_id| served[*client_id] = true);
+      self.chosen.push(index);
+    }
+
+    true
+  }
+}
+
+///
+/// Assembles from the routes a solution better than the incumbent, serving each client at most once with the
+/// vehicles of each type, minimizing the cost of the routes plus the prizes of the unserved clients. Starts from
+/// the columns of the incumbent routes, and each of lns_iters times frees the clients of lns_routes related
+/// columns and partitions them again by branch and bound, with up to max_nodes nodes. Returns None if the
+/// routes of the incumbent aren't in the pool or no better solution is found.
+pub fn set_partitioning(
+  problem: &ProblemInstance,
+  routes: Vec<RouteEntry>,
+  incumbent: &Solution,
+  config: &RoutePoolConfig,
+) -> Option<Solution> {
+  let type_vehicles = problem.type_vehicles();
+
+  let columns: Vec<Column> = routes.into_iter().map(|route| {
+    let clients = route_clients(problem, &route);
+
+    Column {
+      vehicle_type: problem.vehicle(route.vehicle_id).vehicle_type,
+      cost: route.route_cost(),
+      clients,
+      route,
+    }
+  }).collect();
+
+  let mut client_columns: Vec<Vec<usize>> = vec![vec![]; problem.clients.len()];
+  for (index, column) in columns.iter().enumerate() {
+    for client_id in column.clients.iter() {
+      client_columns[*client_id].push(index);
+    }
+  }
+  for column_indexes in client_columns.iter_mut() {
+    column_indexes.sort_by(|i1, i2| columns[*i1].cost.partial_cmp(&columns[*i2].cost).unwrap());
+  }
+
+  let lower_bounds: Vec<Cost> = (0..problem.clients.len()).map(|client_id| {
+    let cheapest = client_columns[client_id].iter()
+      .map(|index| columns[*index].cost / columns[*index].clients.len() as Cost)
+      .fold(f64::INFINITY, f64::min);
+
+    cheapest.min(problem.clients[client_id].prize.unwrap_or(f64::INFINITY))
+  }).collect();
+
+  /* The incumbent routes, or the cheapest ones in the pool with the same clients */
+  let mut chosen = vec![];
+  for route in incumbent.routes.iter() {
+    let vehicle_type = problem.vehicle(route.vehicle_id).vehicle_type;
+    let clients = route_clients(problem, route);
+    let column = client_columns[*clients.first()?].iter()
+      .find(|index| columns[**index].vehicle_type == vehicle_type && columns[**index].clients == clients)?;
+
+    chosen.push(*column);
+  }
+
+  let mut served = vec![false; problem.clients.len()];
+  for index in chosen.iter() {
+    columns[*index].clients.iter().for_each(|client_id| served[*client_id] = true);
+  }
+
+  let mut search = PartitioningSearch { problem, config, columns, client_columns, lower_bounds, type_vehicles, chosen };
+  for _ in 0..config.lns_iters {
+    search.improve(&mut served);
+  }
+
+  let mut next_vehicle = vec![0; search.type_vehicles.len()];
+  let mut sol = Solution { weight_config_name: "set partitioning".to_string(), ..Default::default() };
+
+  for column in search.chosen.iter().map(|index| &search.columns[*index]) {
+    let vehicle_id = search.type_vehicles[column.vehicle_type][next_vehicle[column.vehicle_type]];
+    next_vehicle[column.vehicle_type] += 1;
+
+    sol.routes.push(problem.build_route(vehicle_id, &column.route.visits())?);
+  }
+
+  sol.unserved_clients = (0..problem.clients.len())
+    .filter(|client_id| *client_id != problem.source && !served[*client_id])
+    .collect();
+  problem.evaluate_sol(&mut sol);
+  sol.construction_value = sol.value;
+
+  if sol.is_better_than(incumbent) {
+    Some(sol)
+  } else {
+    None
+  }
+}
+
+///
+/// Clients served by the route, sorted.
+fn route_clients(problem: &ProblemInstance, route: &RouteEntry) -> Vec<usize> {
+  let mut clients: Vec<usize> = route.visits().iter()
+    .map(|visit| visit.client_id)
+    .filter(|client_id| *client_id != problem.source)
+    .collect();
+  clients.sort_unstable();
+  clients.dedup();
+
+  clients
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use crate::types::test_instances::instance;
+  use super::*;
+
+  #[test]
+  fn route_pool_removes_the_greater_cost_per_client() {
+    let problem = instance(json!({
+      "vehicle_definitions": [{ "count": 3 }],
+      "clients": [{}, { "demand": 1 }, { "demand": 1 }, { "demand": 1 }],
+    }), &[0.0, 1.0, 3.0, 5.0]);
+    let route = |clients: Vec<usize>| {
+      let visits: Vec<_> = clients.into_iter().map(|client_id| problem.visit(client_id)).collect();
+      problem.build_route(0, &visits).unwrap()
+    };
+    let pooled = |pool: &RoutePool| {
+      let mut clients: Vec<Vec<usize>> = pool.routes().iter().map(|route| route_clients(&problem, route)).collect();
+      clients.sort();
+      clients
+    };
+
+    let mut pool = RoutePool::new(2);
+    pool.add(&problem, &route(vec![0, 3, 0]));
+    pool.add(&problem, &route(vec![0, 1, 0]));
+    pool.add(&problem, &route(vec![0, 2, 0]));
+    assert_eq!(pooled(&pool), vec![vec![1], vec![2]]);
+
+    pool.add(&problem, &route(vec![0, 2, 1, 0]));
+    assert_eq!(pooled(&pool), vec![vec![1], vec![1, 2]]);
+  }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+
+use rand::Rng;
+
+use crate::types::{ClientKind, Load, ProblemInstance, RouteEntry};
+
+use super::types::ConstructionResult;
+use super::utils::cheapest_insertion;
+
+///
+/// Builds the routes with the sweep heuristic, cluster first and route second. Clients are ordered by their
+/// polar angle around the source, starting from a random one in a random direction. Each cluster takes the next
+/// clients while they fit the vehicle capacity and an estimate of the time left on its shift, then the clients
+/// are inserted on the cheapest position by latest order. Clients that couldn't be inserted start the next cluster.
+/// Clients of the highest service rank are swept first when some clients may be left unserved.
+pub fn sweep_construction(problem: &ProblemInstance) -> ConstructionResult {
+  let mut result = ConstructionResult::new(problem);
+  let mut used_vehicles: HashSet<usize> = HashSet::new();
+  let mut pending = sweep_order(problem);
+
+  loop {
+    pending.retain(|client_id| result.unrouted.contains(client_id));
+
+    let mut ranked = pending.clone();
+    problem.highest_ranked(&mut ranked, |client_id| *client_id);
+
+    let first_client_id = match ranked.first() {
+      Some(client_id) => *client_id,
+      None => break,
+    };
+
+    let vehicle_id = match choose_vehicle(problem, &used_vehicles, first_client_id, &result) {
+      Some(vehicle_id) => vehicle_id,
+      None => {
+        /* No idle vehicle can serve the client, it's left unrouted */
+        pending.retain(|client_id| *client_id != first_client_id);
+        continue
+      },
+    };
+
+    let mut cluster = cluster_clients(problem, vehicle_id, &ranked, &result);
+    cluster.sort_by(|c1, c2| problem.clients[*c1].latest.partial_cmp(&problem.clients[*c2].latest).unwrap());
+
+    let mut route = RouteEntry { vehicle_id, ..Default::default() };
+    for client_id in cluster {
+      if let Some((new_route, _)) = cheapest_insertion(problem, &route, client_id, &result) {
+        result.deliver_on(client_id, &new_route);
+        route = new_route;
+      }
+    }
+
+    used_vehicles.insert(vehicle_id);
+    if !route.clients.is_empty() {
+      result.routes.push(route);
+    }
+  }
+
+  result
+}
+
+///
+/// Clients ordered by polar angle around the source, from a random client in a random direction.
+fn sweep_order(problem: &ProblemInstance) -> Vec<usize> {
+  let [source_x, source_y] = problem.clients[problem.source].pos;
+  let mut clients: Vec<(usize, f64)> = problem.clients.iter()
+    .filter(|client| client.id != problem.source)
+    .map(|client| (client.id, (client.pos[1] - source_y).atan2(client.pos[0] - source_x)))
+    .collect();
+  clients.sort_by(|(_, a1), (_, a2)| a1.partial_cmp(a2).unwrap());
+
+  let mut order: Vec<usize> = clients.into_iter().map(|(client_id, _)| client_id).collect();
+  let mut rng = rand::thread_rng();
+
+  if !order.is_empty() {
+    let first = rng.gen_range(0, order.len());
+    order.rotate_left(first);
+  }
+  if rng.gen::<bool>() {
+    order.reverse();
+  }
+
+  order
+}
+
+///
+/// Idle vehicle that can serve client_id alone whose type has more idle vehicles left.
+fn choose_vehicle(
+  problem: &ProblemInstance,
+  used_vehicles: &HashSet<usize>,
+  client_id: usize,
+  result: &ConstructionResult,
+) -> Option<usize> {
+  let idle_count = problem.idle_count(used_vehicles);
+  problem.idle_vehicle_per_type(used_vehicles).into_iter()
+    .filter(|vehicle_id| {
+      let route = RouteEntry { vehicle_id: *vehicle_id, ..Default::default() };
+      cheapest_insertion(problem, &route, client_id, result).is_some()
+    })
+    .max_by_key(|vehicle_id| idle_count[problem.vehicle(*vehicle_id).vehicle_type])
+}
+
+///
+/// Next pending clients vehicle_id can serve, starting with the first one, while their demand fits the capacity of its trips and the time
+/// to visit them in sweep order fits the vehicle shift.
+fn cluster_clients(
+  problem: &ProblemInstance,
+  vehicle_id: usize,
+  pending: &[usize],
+  result: &ConstructionResult,
+) -> Vec<usize> {
+  let vehicle = problem.vehicle(vehicle_id);
+  let source = &problem.clients[problem.source];
+  let start = problem.route_start(vehicle_id);
+  let horizon = vehicle.shift_end.unwrap_or(source.latest) - start.leave_time;
+
+  let mut capacity = Load::default();
+  for _ in 0..vehicle.max_trips {
+    capacity.add(&vehicle.capacity);
+  }
+
+  let mut cluster = vec![];
+  let mut linehaul_load = Load::default();
+  let mut backhaul_load = Load::default();
+  let mut time = 0.0;
+  let mut last_client_id = problem.source;
+
+  for client_id in pending.iter().filter(|client_id| problem.can_serve(vehicle_id, **client_id)) {
+    let delivered = problem.split_delivery(vehicle_id, &result.demands_left[*client_id]);
+    let load = match problem.clients[*client_id].kind {
+      ClientKind::Linehaul => &mut linehaul_load,
+      ClientKind::Backhaul => &mut backhaul_load,
+    };
+    load.add(&delivered);
+
+    let arc_time = problem.distances[last_client_id][*client_id] / vehicle.speed_factor;
+    let client_time = arc_time + problem.service_time(vehicle_id, *client_id);
+    let back_time = problem.distances[*client_id][problem.source] / vehicle.speed_factor;
+
+    /* The first client is always taken, it can be served alone */
+    let exceeded = !load.fits_in(&capacity) || time + client_time + back_time > horizon;
+    if exceeded && !cluster.is_empty() {
+      break
+    }
+
+    cluster.push(*client_id);
+    time += client_time;
+    last_client_id = *client_id;
+  }
+
+  cluster
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+
+  use serde_json::json;
+
+  use crate::types::test_instances::instance;
+  use super::sweep_construction;
+
+  #[test]
+  fn sweep_construction_serves_mandatory_clients_before_optional_ones() {
+    let problem = instance(json!({
+      "clients": [{}, { "pos": [0, 1], "prize": 100 }, { "pos": [1, 0] }],
+    }), &[0.0, 1.0, 5.0]);
+
+    for _ in 0..10 {
+      let result = sweep_construction(&problem);
+      assert_eq!(result.unrouted, HashSet::from([1]));
+    }
+  }
+}
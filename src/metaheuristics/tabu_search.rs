@@ -0,0 +1,91 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::types::{RouteEntry, Solution};
+
+///
+/// Consecutive visits of the route, skipping the break stops.
+fn route_arcs(route: &RouteEntry) -> impl Iterator<Item = (usize, usize)> + '_ {
+  let visits: Vec<usize> = route.clients.iter()
+    .filter(|rc| !rc.is_break)
+    .map(|rc| rc.client_id)
+    .collect();
+
+  (1..visits.len()).map(move |index| (visits[index - 1], visits[index]))
+}
+
+fn route_assignments(route: &RouteEntry) -> impl Iterator<Item = (usize, usize)> + '_ {
+  route.clients.iter()
+    .filter(|rc| !rc.is_break)
+    .map(move |rc| (rc.client_id, route.vehicle_id))
+}
+
+///
+/// Attributes the tabu search forbids to restore: the arcs removed and the clients removed from a vehicle,
+/// each one until the iteration it expires.
+pub struct TabuList {
+  tenure: usize,
+  iteration: usize,
+  arcs: HashMap<(usize, usize), usize>,
+  assignments: HashMap<(usize, usize), usize>,
+  /* change of the value reaching a new best solution, tabu moves below it are aspirated */
+  aspiration: f64,
+}
+
+impl TabuList {
+  pub fn new(tenure: usize) -> Self {
+    TabuList {
+      tenure,
+      iteration: 0,
+      arcs: HashMap::new(),
+      assignments: HashMap::new(),
+      aspiration: 0.0,
+    }
+  }
+
+  fn is_tabu(attributes: &HashMap<(usize, usize), usize>, attribute: &(usize, usize), iteration: usize) -> bool {
+    attributes.get(attribute).is_some_and(|expiry| *expiry > iteration)
+  }
+
+  ///
+  /// Checks if the move from routes to new_routes, changing the value by delta, adds no tabu arc nor
+  /// assigns a client to a tabu vehicle, or else improves the best value.
+  pub fn allows(&self, routes: (&RouteEntry, &RouteEntry), new_routes: (&RouteEntry, &RouteEntry), delta: f64) -> bool {
+    if delta < self.aspiration {
+      return true
+    }
+
+    let old_arcs: HashSet<(usize, usize)> = route_arcs(routes.0).chain(route_arcs(routes.1)).collect();
+    let old_assignments: HashSet<(usize, usize)> = route_assignments(routes.0)
+      .chain(route_assignments(routes.1))
+      .collect();
+
+    let restores_arc = route_arcs(new_routes.0).chain(route_arcs(new_routes.1))
+      .filter(|arc| !old_arcs.contains(arc))
+      .any(|arc| TabuList::is_tabu(&self.arcs, &arc, self.iteration));
+    let restores_assignment = route_assignments(new_routes.0).chain(route_assignments(new_routes.1))
+      .filter(|assignment| !old_assignments.contains(assignment))
+      .any(|assignment| TabuList::is_tabu(&self.assignments, &assignment, self.iteration));
+
+    !restores_arc && !restores_assignment
+  }
+
+  ///
+  /// Makes tabu the attributes of sol missing on new_sol, and sets the aspiration for the next move from
+  /// new_sol given the best value.
+  pub fn add_move(&mut self, sol: &Solution, new_sol: &Solution, best_value: f64) {
+    self.iteration += 1;
+    let expiry = self.iteration + self.tenure;
+
+    let new_arcs: HashSet<(usize, usize)> = new_sol.routes.iter().flat_map(route_arcs).collect();
+    let new_assignments: HashSet<(usize, usize)> = new_sol.routes.iter().flat_map(route_assignments).collect();
+
+    for arc in sol.routes.iter().flat_map(route_arcs).filter(|arc| !new_arcs.contains(arc)) {
+      self.arcs.insert(arc, expiry);
+    }
+    for assignment in sol.routes.iter().flat_map(route_assignments).filter(|assignment| !new_assignments.contains(assignment)) {
+      self.assignments.insert(assignment, expiry);
+    }
+
+    self.aspiration = best_value - new_sol.value;
+  }
+}
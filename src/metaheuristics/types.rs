@@ -1,9 +1,11 @@
+use std::collections::HashSet;
 use std::fmt;
 
 use serde::{Serialize, Deserialize};
-use serde_json;
 
-use crate::types::{Time, ProblemInstance, RouteEntryClient};
+use crate::types::{Time, Load, ProblemInstance, RouteEntry, RouteEntryClient, Solution};
+
+use super::tabu_search::TabuList;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GraspWeightConfig {
@@ -14,9 +16,131 @@ pub struct GraspWeightConfig {
   pub display_name: String,
 }
 
+///
+/// Method used to build the initial solution of each GRASP iteration.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GraspConstruction {
+  /* grows the routes of every vehicle appending the next client at their end */
+  #[default]
+  Sequential,
+  /* Solomon's I1, builds one route at a time inserting clients on any position */
+  I1Insertion,
+  /* Clarke-Wright savings, merges the routes of each client */
+  Savings,
+  /* regret-k, inserts first the clients that lose more if not inserted on their cheapest route */
+  Regret,
+  /* sweep, clusters the clients by polar angle around the source and routes each cluster */
+  Sweep,
+}
+
+///
+/// Client that starts each route of the I1 insertion.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum I1Seed {
+  /* farthest from the source */
+  #[default]
+  Farthest,
+  /* with the earliest latest */
+  EarliestDeadline,
+}
+
+///
+/// Criterion to accept the moves of the walk that follows the local search, which may increase the value.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AcceptanceCriterion {
+  /* only improving moves, there's no walk */
+  #[default]
+  Descent,
+  /* accepts an increase d with probability exp(-d / temperature), the temperature cools on each move */
+  SimulatedAnnealing,
+  /* accepts values up to a deviation over the best value of the walk */
+  RecordToRecord,
+  /* accepts values lower than the value of late_acceptance_length moves before */
+  LateAcceptance,
+}
+
+///
+/// Method improving the solutions built by the construction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GraspImprovement {
+  /* descent, followed by the acceptance walk and the oscillation when enabled */
+  #[default]
+  LocalSearch,
+  /* best non tabu 2-OPT or insertion move on each iteration, followed by a descent */
+  TabuSearch,
+}
+
+///
+/// Parameters of Solomon's I1 insertion criteria. Clients are inserted where
+/// c1 = alpha * (d(i, u) + d(u, j) - mu * d(i, j)) + (1 - alpha) * push forward of j is minimum,
+/// and the client inserted is the one that maximizes c2 = lambda * d(source, u) - c1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct I1Config {
+  pub mu: f64,
+  pub lambda: f64,
+  pub alpha: f64,
+  pub seed: I1Seed,
+}
+
+impl Default for I1Config {
+  fn default() -> Self {
+    Self {
+      mu: 1.0,
+      lambda: 1.0,
+      alpha: 0.5,
+      seed: I1Seed::Farthest,
+    }
+  }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct GraspConfig {
+  pub construction: GraspConstruction,
+  pub i1_config: I1Config,
+  /* number of greater savings the merge is chosen from */
+  pub savings_rcl_size: usize,
+  /* number of cheapest routes compared by the regret construction, at least 2 */
+  pub regret_k: usize,
+  /* place the clients the construction couldn't, instead of discarding the iteration */
+  pub repair_enabled: bool,
+  /* max number of clients ejected in a chain to make room for another one */
+  pub repair_ejection_depth: usize,
+  /* number of clients closest to the one placed that may be ejected for it */
+  pub repair_ejection_neighbors: usize,
+  /* cost of each client that must be served left unserved by the repair, until the local search serves it */
+  pub repair_penalty: f64,
+  /* search through solutions exceeding capacities and time windows after the local search */
+  pub oscillation_enabled: bool,
+  /* max number of moves of the oscillation */
+  pub oscillation_iters: usize,
+  /* initial penalties per unit of load over capacity and per unit of time exceeded */
+  pub oscillation_capacity_penalty: f64,
+  pub oscillation_time_penalty: f64,
+  /* fraction of the moves ending on a feasible solution the penalties are adjusted to */
+  pub oscillation_target_feasible: f64,
+  /* moves between penalty adjustments */
+  pub oscillation_update_iters: usize,
+  /* walk from the local optimum accepting moves that may increase the value */
+  pub acceptance: AcceptanceCriterion,
+  /* moves of the walk */
+  pub acceptance_iters: usize,
+  /* initial temperature as a fraction of the value, and its factor after each move */
+  pub annealing_temperature: f64,
+  pub annealing_cooling: f64,
+  /* fraction of the best value accepted over it */
+  pub record_deviation: f64,
+  pub late_acceptance_length: usize,
+  pub improvement: GraspImprovement,
+  /* moves of the tabu search */
+  pub tabu_iters: usize,
+  /* moves a removed arc or client to vehicle assignment stays tabu */
+  pub tabu_tenure: usize,
   pub weight_configs: Vec<GraspWeightConfig>,
   pub rcl_alpha: f64,
   pub rcl_min_size: usize,
@@ -30,11 +154,36 @@ pub struct GraspConfig {
   pub insertion_search_enabled: bool,
   pub insertion_search_first_improvement: bool,
   pub insertion_search_sequence_length: usize,
+  pub prize_search_enabled: bool,
+  pub prize_search_first_improvement: bool,
 }
 
 impl Default for GraspConfig {
   fn default() -> GraspConfig {
     GraspConfig {
+      construction: GraspConstruction::Sequential,
+      i1_config: Default::default(),
+      savings_rcl_size: 3,
+      regret_k: 3,
+      repair_enabled: false,
+      repair_ejection_depth: 2,
+      repair_ejection_neighbors: 10,
+      repair_penalty: 10000.0,
+      oscillation_enabled: false,
+      oscillation_iters: 100,
+      oscillation_capacity_penalty: 10.0,
+      oscillation_time_penalty: 10.0,
+      oscillation_target_feasible: 0.2,
+      oscillation_update_iters: 10,
+      acceptance: AcceptanceCriterion::Descent,
+      acceptance_iters: 300,
+      annealing_temperature: 0.01,
+      annealing_cooling: 0.99,
+      record_deviation: 0.01,
+      late_acceptance_length: 50,
+      improvement: GraspImprovement::LocalSearch,
+      tabu_iters: 100,
+      tabu_tenure: 10,
       weight_configs: vec![
         GraspWeightConfig {
           time_weight: 0.2,
@@ -77,6 +226,8 @@ impl Default for GraspConfig {
       insertion_search_enabled: true,
       insertion_search_first_improvement: true,
       insertion_search_sequence_length: 1,
+      prize_search_enabled: true,
+      prize_search_first_improvement: true,
     }
   }
 }
@@ -87,38 +238,277 @@ impl fmt::Display for GraspConfig {
   }
 }
 
+///
+/// Configuration of the hybrid genetic search, its individuals are educated by the GRASP local search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeneticConfig {
+  /* individuals left by each survivors selection */
+  pub population_size: usize,
+  /* offspring added to the population before a survivors selection */
+  pub generation_size: usize,
+  /* initial individuals built by the GRASP, the rest are random giant tours */
+  pub grasp_seeds: usize,
+  /* best individuals whose fitness doesn't depend on their diversity */
+  pub elite_size: usize,
+  /* closest individuals the diversity contribution is measured to */
+  pub close_size: usize,
+  /* labels kept on each position of the tour by the split, the cheapest ones */
+  pub split_labels: usize,
+}
+
+impl Default for GeneticConfig {
+  fn default() -> GeneticConfig {
+    GeneticConfig {
+      population_size: 25,
+      generation_size: 40,
+      grasp_seeds: 5,
+      elite_size: 4,
+      close_size: 3,
+      split_labels: 10,
+    }
+  }
+}
+
+///
+/// Configuration of the pool of the routes found by the iterations of every thread, and of the set partitioning
+/// that assembles solutions from them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoutePoolConfig {
+  pub enabled: bool,
+  /* max number of routes kept, the ones with the lower cost per client */
+  pub size: usize,
+  /* iterations of each thread between set partitioning solves */
+  pub solve_iters: i32,
+  /* large neighborhood search iterations of each solve */
+  pub lns_iters: usize,
+  /* routes of the current solution whose clients are partitioned again on each iteration */
+  pub lns_routes: usize,
+  /* nodes explored by the branch and bound of each iteration */
+  pub max_nodes: usize,
+}
+
+impl Default for RoutePoolConfig {
+  fn default() -> RoutePoolConfig {
+    RoutePoolConfig {
+      enabled: false,
+      size: 5000,
+      solve_iters: 50,
+      lns_iters: 200,
+      lns_routes: 4,
+      max_nodes: 10000,
+    }
+  }
+}
+
 #[derive(Debug)]
 pub struct GraspRouteMove {
   pub vehicle_id: usize,
   pub target_client_id: usize,
   pub cost: f64,
+  /* go back to the source to reload before moving to the target */
+  pub reload: bool,
+  /* part of the target demand delivered, less than all of it on split deliveries */
+  pub delivered: Load,
 }
 
 #[derive(Default, Debug)]
 pub struct GraspRoute {
   pub vehicle_id: usize,
   pub current_client_id: usize,
-  pub current_time: Time,
-  pub route_time: Time,
-  pub capacity_left: f64,
+  /* times the vehicle got back to the source */
+  pub trips: usize,
   pub route: Vec<RouteEntryClient>,
 }
 
 impl GraspRoute {
   pub fn update(&mut self, target_client_id: usize, problem: &ProblemInstance) {
-    let client_to = &problem.clients[target_client_id];
-    let arc_time = problem.distances[self.current_client_id][target_client_id];
+    self.visit(target_client_id, &problem.clients[target_client_id].demand, problem);
+  }
+
+  ///
+  /// Moves the vehicle to target_client_id delivering part of its demand.
+  pub fn visit(&mut self, target_client_id: usize, delivered: &Load, problem: &ProblemInstance) {
+    /* The route starts at the source when the vehicle shift starts, later visits to it end a trip */
+    if self.route.is_empty() {
+      self.route.push(problem.route_start(self.vehicle_id));
+    } else {
+      if target_client_id == problem.source {
+        self.trips += 1;
+      }
+      problem.append_route_client(self.vehicle_id, &mut self.route, target_client_id);
+    }
+    self.route.last_mut().unwrap().delivered = delivered.clone();
 
-    /* Update route costs */
     self.current_client_id = target_client_id;
-    self.capacity_left -= client_to.demand;
-    self.route_time += arc_time;
+  }
+
+  ///
+  /// Route entry of the current client.
+  pub fn current_route_client(&self) -> &RouteEntryClient {
+    self.route.last().unwrap()
+  }
+
+  ///
+  /// Whether the vehicle can visit client_id with the delivered load on the current trip,
+  /// or on a new trip when reload is set.
+  pub fn can_load(&self, problem: &ProblemInstance, client_id: usize, delivered: &Load, reload: bool) -> bool {
+    let visit = RouteEntryClient { client_id, delivered: delivered.clone(), ..Default::default() };
+    let source = problem.visit(problem.source);
+    let route = if reload { &[] } else { &self.route[..] };
+
+    let visits = std::iter::once(&source)
+      .chain(route.iter().skip(1).filter(|rc| !rc.is_break))
+      .chain(std::iter::once(&visit));
 
-    let route_entry_client = problem.create_route_entry_client(
-      arc_time, target_client_id, self.current_time
-    );
-    self.current_time = route_entry_client.leave_time;
+    problem.is_load_feasible(self.vehicle_id, visits)
+  }
+
+  ///
+  /// Whether the vehicle can go back to the source and start another trip.
+  pub fn can_reload(&self, problem: &ProblemInstance) -> bool {
+    self.current_client_id != problem.source
+    && self.trips + 1 < problem.vehicle(self.vehicle_id).max_trips
+  }
+}
+
+///
+/// Cost per unit of the violations allowed on relaxed routes.
+#[derive(Debug, Clone)]
+pub struct Penalties {
+  pub capacity: f64,
+  pub time: f64,
+}
+
+impl Penalties {
+  ///
+  /// Route cost plus the penalty of its violations.
+  pub fn route_cost(&self, route: &RouteEntry) -> f64 {
+    route.route_cost() + self.capacity * route.capacity_excess + self.time * route.time_excess
+  }
+
+  ///
+  /// Solution value plus the penalty of the violations of its routes.
+  pub fn solution_cost(&self, sol: &Solution) -> f64 {
+    sol.value + sol.routes.iter().map(|route| self.route_cost(route) - route.route_cost()).sum::<f64>()
+  }
+}
+
+///
+/// Conditions the moves of the route searches must meet besides being feasible.
+#[derive(Clone, Copy, Default)]
+pub struct MoveCriteria<'a> {
+  /* relaxes the routes, their cost adds the penalty of the violations */
+  pub penalties: Option<&'a Penalties>,
+  /* max increase of the cost accepted, 0 accepts only improvements */
+  pub threshold: f64,
+  /* rejects the moves restoring tabu attributes, unless aspirated */
+  pub tabu: Option<&'a TabuList>,
+}
+
+impl MoveCriteria<'_> {
+  pub fn route_cost(&self, route: &RouteEntry) -> f64 {
+    self.penalties.map_or(route.route_cost(), |penalties| penalties.route_cost(route))
+  }
+
+  ///
+  /// Checks if the move from route1 and route2 to new_route1 and new_route2, with costs old_value and value,
+  /// is accepted.
+  pub fn accepts(
+    &self,
+    routes: (&RouteEntry, &RouteEntry),
+    new_routes: (&RouteEntry, &RouteEntry),
+    old_value: f64,
+    value: f64,
+  ) -> bool {
+    value < old_value + self.threshold
+    &&
+    self.tabu.is_none_or(|tabu| tabu.allows(routes, new_routes, value - old_value))
+  }
+}
+
+///
+/// Routes built by a construction method and the clients left unrouted.
+pub struct ConstructionResult {
+  pub routes: Vec<RouteEntry>,
+  /* clients with demand left to deliver */
+  pub unrouted: HashSet<usize>,
+  pub demands_left: Vec<Load>,
+}
+
+impl ConstructionResult {
+  pub fn new(problem: &ProblemInstance) -> Self {
+    Self {
+      routes: vec![],
+      unrouted: (0..problem.clients.len()).filter(|index| *index != problem.source).collect(),
+      demands_left: problem.clients.iter().map(|client| client.demand.clone()).collect(),
+    }
+  }
+
+  ///
+  /// Delivers part of the demand left of client_id, which is routed once all of it is delivered.
+  pub fn deliver(&mut self, client_id: usize, delivered: &Load) {
+    self.demands_left[client_id].sub(delivered);
+    if self.demands_left[client_id].is_zero() {
+      self.unrouted.remove(&client_id);
+    }
+  }
+
+  ///
+  /// Clients left unrouted that must be served, because they are not optional or are partially served.
+  pub fn required_clients(&self, problem: &ProblemInstance) -> Vec<usize> {
+    let mut clients: Vec<usize> = self.unrouted.iter()
+      .filter(|client_id| {
+        let client = &problem.clients[**client_id];
+        client.prize.is_none() || self.demands_left[client.id] != client.demand
+      })
+      .cloned()
+      .collect();
+    clients.sort_unstable();
+
+    clients
+  }
+
+  ///
+  /// Delivers the quantity of the last visit to client_id on the route.
+  pub fn deliver_on(&mut self, client_id: usize, route: &RouteEntry) {
+    let visit = route.clients.iter()
+      .rev()
+      .find(|rc| rc.client_id == client_id && !rc.is_break)
+      .unwrap();
+
+    self.deliver(client_id, &visit.delivered);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use crate::types::test_instances::instance;
+  use super::*;
+
+  #[test]
+  fn grasp_route_reaches_max_trips() {
+    let problem = instance(json!({ "vehicle_definitions": [{ "max_trips": 3 }] }), &[0.0, 1.0, 2.0, 3.0, 4.0]);
+
+    let mut route = GraspRoute { vehicle_id: 0, current_client_id: problem.source, ..Default::default() };
+    route.update(problem.source, &problem);
+    assert_eq!(route.trips, 0);
+    assert!(!route.can_reload(&problem));
+
+    for client_id in 1..=3 {
+      route.update(client_id, &problem);
+      assert_eq!(route.can_reload(&problem), client_id < 3);
+
+      if route.can_reload(&problem) {
+        route.update(problem.source, &problem);
+        assert_eq!(route.trips, client_id);
+      }
+    }
 
-    self.route.push(route_entry_client);
+    let trips = route.route.iter().skip(1).filter(|rc| rc.client_id == problem.source).count() + 1;
+    assert_eq!(trips, 3);
   }
 }
@@ -1,19 +1,16 @@
 use std::cmp;
 
-use rand;
 use rand::seq::SliceRandom;
 
-use crate::types::{Cost, Solution, RouteEntry};
+use crate::types::{ProblemInstance, RouteEntry, RouteEntryClient, Solution};
+
+use super::types::ConstructionResult;
 
 /// Assumes the list is sorted
 /// Returns an entry of the list from the first 0..size elements
 #[allow(dead_code)]
-pub fn sized_rcl_choose<'a, T>(list: &'a Vec<T>, size: usize) -> Option<&'a T> {
-  let mut rcl: Vec<&T> = vec![];
-
-  for index in 0..cmp::min(size, list.len()) {
-    rcl.push(&list[index]);
-  }
+pub fn sized_rcl_choose<T>(list: &[T], size: usize) -> Option<&T> {
+  let rcl: Vec<&T> = list.iter().take(size).collect();
 
   match rcl.choose(&mut rand::thread_rng()) {
     None => None,
@@ -24,15 +21,8 @@ pub fn sized_rcl_choose<'a, T>(list: &'a Vec<T>, size: usize) -> Option<&'a T> {
 
 // Return the index of the maximun item that satisfies that:
 // cost item belongs to [c_min, c_min + (c_max - c_min) * alpha) 
-pub fn alpha_max_index(costs: &Vec<f64>, alpha: f64) -> Option<usize> {
-  let c_min: f64;
-
-  match costs.first() {
-    Some(first_cost) => {
-      c_min = *first_cost;
-    },
-    None => return None,
-  }
+pub fn alpha_max_index(costs: &[f64], alpha: f64) -> Option<usize> {
+  let c_min = *costs.first()?;
 
   let c_max = costs.last().unwrap();
   let max_cost = c_min + (c_max - c_min) * alpha;
@@ -41,9 +31,9 @@ pub fn alpha_max_index(costs: &Vec<f64>, alpha: f64) -> Option<usize> {
 
   match costs.binary_search_by(|cost| cost.partial_cmp(&max_cost).unwrap()) {
     Ok(match_index) => {
-      for index in match_index..costs.len() {
+      for (index, cost) in costs.iter().enumerate().skip(match_index) {
         max_index = index;
-        if costs[index] > max_cost {
+        if *cost > max_cost {
           break;
         }
       }
@@ -61,9 +51,9 @@ pub fn alpha_max_index(costs: &Vec<f64>, alpha: f64) -> Option<usize> {
 /// c_min <= cost <= c_min + (c_max - c_min) * alpha
 #[allow(dead_code)]
 pub fn alpha_rcl_choose<'a, T>(
-  list: &'a Vec<T>, costs: &Vec<f64>, alpha: f64, min_size: usize,
+  list: &'a [T], costs: &[f64], alpha: f64, min_size: usize,
 ) -> Option<&'a T> {
-  if let Some(max_index) = alpha_max_index(&costs, alpha) {
+  if let Some(max_index) = alpha_max_index(costs, alpha) {
     sized_rcl_choose(list, cmp::min(cmp::max(max_index, min_size), list.len()))
   } else {
     None
@@ -73,7 +63,7 @@ pub fn alpha_rcl_choose<'a, T>(
 /// Returns an entry of the list from the list elements given the probability
 /// specify by the weights list.
 #[allow(dead_code)]
-pub fn weighted_choose<'a, T>(list: &'a Vec<T>, weights: Vec<f64>) -> Option<&'a T> {
+pub fn weighted_choose<T>(list: &[T], weights: Vec<f64>) -> Option<&T> {
   let rcl: Vec<(usize, &T)> = list.iter().enumerate().collect();
 
   match rcl.choose_weighted(&mut rand::thread_rng(), |(index, _)| weights[*index]) {
@@ -82,6 +72,21 @@ pub fn weighted_choose<'a, T>(list: &'a Vec<T>, weights: Vec<f64>) -> Option<&'a
   }
 }
 
+///
+/// Creates a new solution by replacing the route done by the same vehicle, the route
+/// is added if the vehicle wasn't used.
+pub fn replace_route(sol: &Solution, new_route: &RouteEntry) -> Solution {
+  let mut new_sol = sol.clone();
+
+  match new_sol.routes.iter().position(|route| route.vehicle_id == new_route.vehicle_id) {
+    Some(index) => new_sol.routes[index] = new_route.clone(),
+    None => new_sol.routes.push(new_route.clone()),
+  }
+  new_sol.routes.retain(|r| !r.clients.is_empty());
+
+  new_sol
+}
+
 ///
 /// Creates a new solution by replacing the two routes
 pub fn transform_solution(sol: &Solution, new_route1: &RouteEntry, new_route2: &RouteEntry) -> Solution {
@@ -99,7 +104,57 @@ pub fn transform_solution(sol: &Solution, new_route1: &RouteEntry, new_route2: &
       }
     })
   }
-  new_sol.routes = new_routes.into_iter().filter(|r| r.route_cost() > 0 as Cost).collect();
+  new_sol.routes = new_routes.into_iter().filter(|r| !r.clients.is_empty()).collect();
 
   new_sol
 }
+
+///
+/// Inserts client_id on the position of the route that increases its value the least,
+/// the route may be empty. Returns the new route and the increase.
+pub fn cheapest_insertion(
+  problem: &ProblemInstance,
+  route: &RouteEntry,
+  client_id: usize,
+  result: &ConstructionResult,
+) -> Option<(RouteEntry, f64)> {
+  if !problem.can_serve(route.vehicle_id, client_id) {
+    return None
+  }
+
+  let delivered = problem.split_delivery(route.vehicle_id, &result.demands_left[client_id]);
+  let visit = RouteEntryClient { delivered, ..problem.visit(client_id) };
+
+  cheapest_visit_insertion(problem, route, &visit)
+}
+
+///
+/// Inserts the visit on the position of the route that increases its value the least,
+/// the route may be empty. Returns the new route and the increase.
+pub fn cheapest_visit_insertion(
+  problem: &ProblemInstance,
+  route: &RouteEntry,
+  visit: &RouteEntryClient,
+) -> Option<(RouteEntry, f64)> {
+  if !problem.can_serve(route.vehicle_id, visit.client_id) {
+    return None
+  }
+
+  let visits = if route.clients.is_empty() {
+    vec![problem.visit(problem.source), problem.visit(problem.source)]
+  } else {
+    route.visits()
+  };
+
+  (1..visits.len())
+    .filter_map(|position| {
+      let mut new_visits = visits.clone();
+      new_visits.insert(position, visit.clone());
+      problem.build_route(route.vehicle_id, &new_visits)
+    })
+    .map(|new_route| {
+      let cost = new_route.route_cost() - route.route_cost();
+      (new_route, cost)
+    })
+    .min_by(|(_, c1), (_, c2)| c1.partial_cmp(c2).unwrap())
+}
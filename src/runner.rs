@@ -1,20 +1,69 @@
-use crossbeam;
+use std::sync::Mutex;
+
+use crate::types::{Config, Metaheuristic, ParetoArchive, ProblemInstance, Solution};
+use crate::metaheuristics::{Genetic, Grasp, RoutePool, set_partitioning};
+
+///
+/// Best solution found and the solutions not dominated, if the multi objective mode is on.
+pub struct RunResult {
+  pub best: Solution,
+  pub pareto_front: Vec<Solution>,
+  /* iterations whose construction was repaired into a solution */
+  pub repaired_iters: usize,
+}
 
-use crate::types::{Config, ProblemInstance, Solution};
-use crate::metaheuristics::Grasp;
+///
+/// Replaces best with sol if it's better.
+fn update_best(best: &mut Option<Solution>, sol: Solution, thread_id: i32, current_iter: i32) {
+  match best.as_ref() {
+    None => *best = Some(sol),
+    Some(current) => {
+      if sol.is_better_than(current) {
+        info!(
+          "thread={} iteration={} best_value={} construction_value={} weight_config={}",
+          thread_id, current_iter, &sol.value, &sol.construction_value, &sol.weight_config_name,
+        );
+        *best = Some(sol);
+      }
+    }
+  }
+}
+
+///
+/// Assembles a solution of the routes in the pool better than best, if found, improved by the local search.
+fn solve_pool(config: &Config, instance: &ProblemInstance, pool: &Mutex<RoutePool>, best: Option<&Solution>) -> Option<Solution> {
+  let routes = pool.lock().unwrap().routes();
+  let sol = set_partitioning(instance, routes, best?, &config.route_pool_config)?;
+  let mh = Grasp { config: config.grasp_config.clone() };
 
-fn do_run(thread_id: i32, config: &Config, instance: &ProblemInstance) -> Option<Solution> {
+  mh.improve(sol, instance).ok()
+}
+
+fn do_run(
+  thread_id: i32,
+  config: &Config,
+  instance: &ProblemInstance,
+  pool: &Mutex<RoutePool>,
+) -> (Option<Solution>, ParetoArchive, usize) {
   let mut iteration = config.iters;
   let mut best: Option<Solution> = None;
+  let mut archive = ParetoArchive::default();
   let mh: Grasp = Grasp { config: config.grasp_config.clone() };
+  let mut genetic = Genetic::new(config.genetic_config.clone(), Grasp { config: config.grasp_config.clone() });
   let mut error_count = 0;
+  let mut repaired_count = 0;
   let mut last_error: String = "".to_string();
 
   while iteration != 0 {
     iteration -= 1;
     let current_iter = config.iters - iteration;
 
-    let sol = match mh.iterate(&instance) {
+    let result = match config.metaheuristic {
+      Metaheuristic::Grasp => mh.iterate(instance),
+      Metaheuristic::Genetic => genetic.iterate(instance),
+    };
+
+    let sol = match result {
       Err(error) => {
         last_error = error;
         error_count += 1;
@@ -26,17 +75,27 @@ fn do_run(thread_id: i32, config: &Config, instance: &ProblemInstance) -> Option
       },
     };
 
+    if sol.repaired {
+      repaired_count += 1;
+    }
+
+    if config.multi_objective {
+      archive.add(sol.clone());
+    }
+
+    if config.route_pool_config.enabled {
+      pool.lock().unwrap().add_solution(instance, &sol);
+    }
+
+    update_best(&mut best, sol, thread_id, current_iter);
 
-    match best.as_ref() {
-      None => best = Some(sol),
-      Some(current) => {
-        if current.value > sol.value {
-          info!(
-            "thread={} iteration={} best_value={} construction_value={} weight_config={}",
-            thread_id, current_iter, &sol.value, &sol.construction_value, &sol.weight_config_name,
-          );
-          best = Some(sol);
+    if config.route_pool_config.enabled && current_iter % config.route_pool_config.solve_iters.max(1) == 0 {
+      if let Some(sol) = solve_pool(config, instance, pool, best.as_ref()) {
+        if config.multi_objective {
+          archive.add(sol.clone());
         }
+
+        update_best(&mut best, sol, thread_id, current_iter);
       }
     }
   }
@@ -46,19 +105,25 @@ fn do_run(thread_id: i32, config: &Config, instance: &ProblemInstance) -> Option
     error!("thread={} last_error={}", thread_id, last_error);
   }
 
-  best
+  if repaired_count > 0 {
+    info!("thread={} repaired_iters={}", thread_id, repaired_count);
+  }
+
+  (best, archive, repaired_count)
 }
 
-pub fn run(config: &Config, instance: &ProblemInstance) -> Option<Solution> {
+pub fn run(config: &Config, instance: &ProblemInstance) -> Option<RunResult> {
   info!("Using configuration:\n{}\nInstance{}\n", config, instance);
 
   let mut results = vec![];
+  let pool = Mutex::new(RoutePool::new(config.route_pool_config.size));
 
   crossbeam::scope(|s| {
     let mut handlers = vec![];
 
     for index in 0..config.number_of_threads {
-      let handle = s.spawn(move |_| do_run(index + 1, config, instance));
+      let pool = &pool;
+      let handle = s.spawn(move |_| do_run(index + 1, config, instance, pool));
       handlers.push(handle);
     }
 
@@ -74,9 +139,10 @@ pub fn run(config: &Config, instance: &ProblemInstance) -> Option<Solution> {
     return None;
   }
 
-  let mut ret = results.pop().unwrap();
-  while !results.is_empty() {
-    let current = results.pop().unwrap();
+  let (mut ret, mut archive, mut repaired_iters) = results.pop().unwrap();
+  while let Some((current, thread_archive, thread_repaired_iters)) = results.pop() {
+    archive.merge(thread_archive);
+    repaired_iters += thread_repaired_iters;
 
     if ret.is_none() {
       ret = current;
@@ -84,11 +150,26 @@ pub fn run(config: &Config, instance: &ProblemInstance) -> Option<Solution> {
       let s1 = ret.as_ref().unwrap();
       let s2 = current.as_ref().unwrap();
 
-      if s2.value < s1.value {
+      if s2.is_better_than(s1) {
         ret = current;
       }
     }
   }
 
-  ret
+  /* The routes of every thread together may assemble a better solution */
+  if config.route_pool_config.enabled {
+    if let Some(sol) = solve_pool(config, instance, &pool, ret.as_ref()) {
+      info!("Set partitioning of the route pool best_value={}", sol.value);
+
+      if config.multi_objective {
+        archive.add(sol.clone());
+      }
+
+      if ret.as_ref().is_none_or(|best| sol.is_better_than(best)) {
+        ret = Some(sol);
+      }
+    }
+  }
+
+  ret.map(|best| RunResult { best, pareto_front: archive.into_solutions(), repaired_iters })
 }
@@ -1,27 +1,124 @@
 use std::fmt;
 use serde::{Serialize, Deserialize};
-use serde_json;
 
-use crate::metaheuristics::GraspConfig;
+use crate::metaheuristics::{GeneticConfig, GraspConfig, RoutePoolConfig};
 
 use super::{ProblemInstance, Solution};
 
+///
+/// Weights of the objective components, every component is computed for each route.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Objective {
+  /* vehicle fixed_cost of each route */
+  pub fixed_cost: f64,
+  /* vehicle variable_cost times the distance */
+  pub variable_cost: f64,
+  pub distance: f64,
+  /* time from leaving the source until getting back */
+  pub duration: f64,
+  /* vehicle wait_cost times the time waited */
+  pub waiting: f64,
+  /* time served after the latest of the clients */
+  pub lateness: f64,
+  /* early_deviation_penalty times the time served before the earliest of the clients */
+  pub early: f64,
+  /* number of routes */
+  pub routes: f64,
+}
+
+impl Objective {
+  ///
+  /// Fixed and variable costs of the vehicles, plus the waiting and early service costs.
+  pub fn cost() -> Self {
+    Self {
+      fixed_cost: 1.0,
+      variable_cost: 1.0,
+      waiting: 1.0,
+      early: 1.0,
+      ..Self::none()
+    }
+  }
+
+  ///
+  /// Sum of the route distances.
+  pub fn distance() -> Self {
+    Self {
+      distance: 1.0,
+      ..Self::none()
+    }
+  }
+
+  fn none() -> Self {
+    Self {
+      fixed_cost: 0.0,
+      variable_cost: 0.0,
+      distance: 0.0,
+      duration: 0.0,
+      waiting: 0.0,
+      lateness: 0.0,
+      early: 0.0,
+      routes: 0.0,
+    }
+  }
+}
+
+impl Default for Objective {
+  fn default() -> Self {
+    Self::cost()
+  }
+}
+
+///
+/// Metaheuristic run by each thread.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metaheuristic {
+  #[default]
+  Grasp,
+  /* hybrid genetic search, each iteration adds an individual to the population */
+  Genetic,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Config {
   pub iters: i32,
+  pub metaheuristic: Metaheuristic,
   pub grasp_config: GraspConfig,
+  pub genetic_config: GeneticConfig,
+  /* assembles solutions from the routes of every iteration by set partitioning */
+  pub route_pool_config: RoutePoolConfig,
   pub number_of_threads: i32,
   pub optimize_cost: bool,
+  /* when missing, optimize_cost chooses between the cost and distance objectives */
+  pub objective: Option<Objective>,
+  /* keep the solutions not dominated on fixed cost, distance, lateness, routes and unserved penalty */
+  pub multi_objective: bool,
 }
 
 impl Default for Config {
   fn default() -> Self {
     Self {
       iters: 10,
+      metaheuristic: Default::default(),
       grasp_config: Default::default(),
+      genetic_config: Default::default(),
+      route_pool_config: Default::default(),
       number_of_threads: 1,
       optimize_cost: true,
+      objective: None,
+      multi_objective: false,
+    }
+  }
+}
+
+impl Config {
+  pub fn objective(&self) -> Objective {
+    match &self.objective {
+      Some(objective) => objective.clone(),
+      None if self.optimize_cost => Objective::cost(),
+      None => Objective::distance(),
     }
   }
 }
@@ -36,5 +133,9 @@ impl fmt::Display for Config {
 pub struct Output {
   pub name: String,
   pub solution: Solution,
+  /* solutions not dominated, only on the multi objective mode */
+  pub pareto_front: Vec<Solution>,
+  /* iterations whose construction was repaired into a solution */
+  pub repaired_iters: usize,
   pub instance: ProblemInstance,
 }
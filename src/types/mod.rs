@@ -2,6 +2,8 @@ mod others;
 mod solution;
 mod problem_instance;
 mod config;
+#[cfg(test)]
+pub mod test_instances;
 
 pub use config::*;
 pub use solution::*;
@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use std::fmt;
 
 use serde::{Serialize, Deserialize};
@@ -5,21 +6,257 @@ use serde::{Serialize, Deserialize};
 pub type Time = f64;
 pub type Cost = f64;
 
+///
+/// Capacity or demand over several dimensions (weight, volume, pallets, ...).
+/// It's read either from a number, for a single dimension, or from a list of numbers.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "LoadDefinition")]
+pub struct Load(pub Vec<f64>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LoadDefinition {
+  Scalar(f64),
+  Vector(Vec<f64>),
+}
+
+impl From<LoadDefinition> for Load {
+  fn from(definition: LoadDefinition) -> Self {
+    match definition {
+      LoadDefinition::Scalar(value) => Load(vec![value]),
+      LoadDefinition::Vector(values) => Load(values),
+    }
+  }
+}
+
+impl Load {
+  pub fn dimension(&self) -> usize {
+    self.0.len()
+  }
+
+  pub fn add(&mut self, other: &Load) {
+    if self.0.len() < other.0.len() {
+      self.0.resize(other.0.len(), 0.0);
+    }
+
+    for (value, other_value) in self.0.iter_mut().zip(other.0.iter()) {
+      *value += other_value;
+    }
+  }
+
+  pub fn sub(&mut self, other: &Load) {
+    if self.0.len() < other.0.len() {
+      self.0.resize(other.0.len(), 0.0);
+    }
+
+    for (value, other_value) in self.0.iter_mut().zip(other.0.iter()) {
+      *value -= other_value;
+    }
+  }
+
+  ///
+  /// Whether every dimension is less or equal than the capacity one.
+  pub fn fits_in(&self, capacity: &Load) -> bool {
+    self.0.iter().enumerate().all(|(index, value)| {
+      *value <= capacity.0.get(index).cloned().unwrap_or(0.0)
+    })
+  }
+
+  ///
+  /// Sum of the excess over the capacity of each dimension.
+  pub fn excess(&self, capacity: &Load) -> f64 {
+    self.0.iter().enumerate().map(|(index, value)| {
+      (value - capacity.0.get(index).cloned().unwrap_or(0.0)).max(0.0)
+    }).sum()
+  }
+
+  ///
+  /// Minimum of each dimension.
+  pub fn min(&self, other: &Load) -> Load {
+    Load(self.0.iter().enumerate().map(|(index, value)| {
+      value.min(other.0.get(index).cloned().unwrap_or(0.0))
+    }).collect())
+  }
+
+  ///
+  /// Whether no dimension is positive.
+  pub fn is_zero(&self) -> bool {
+    self.0.iter().all(|value| *value <= 0.0)
+  }
+
+  ///
+  /// Ratio between each dimension and the capacity one.
+  pub fn utilization(&self, capacity: &Load) -> Vec<f64> {
+    capacity.0.iter().enumerate().map(|(index, capacity_value)| {
+      self.0.get(index).cloned().unwrap_or(0.0) / capacity_value
+    }).collect()
+  }
+}
+
+impl fmt::Display for Load {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0.iter().map(|value| value.to_string()).collect::<Vec<String>>().join("/"))
+  }
+}
+
 #[derive(Debug, Default, Serialize)]
 pub struct Vehicle {
   /* id is the index */
   pub id: usize,
-  pub capacity: f64,
+  pub capacity: Load,
   pub fixed_cost: Cost,
   pub variable_cost: Cost,
+  pub speed_factor: f64,
+  pub service_time_factor: f64,
+  /* index of the vehicle definition */
+  pub vehicle_type: usize,
+  pub skills: Vec<String>,
+  pub max_trips: usize,
+  pub reload_time: Time,
+  pub shift_start: Option<Time>,
+  pub shift_end: Option<Time>,
+  pub max_driving_time: Option<Time>,
+  pub break_window: Option<[Time; 2]>,
+  pub break_duration: Time,
+  pub wait_cost: Cost,
+  pub max_ride_time: Option<Time>,
+}
+
+///
+/// Number of vehicles of a definition, read either from a number or from "unlimited".
+/// Vehicles of unlimited definitions are created on demand, when the previous one gets a route.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "VehicleCountDefinition", into = "VehicleCountDefinition")]
+pub enum VehicleCount {
+  Limited(usize),
+  Unlimited,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum VehicleCountDefinition {
+  Count(usize),
+  Name(String),
+}
+
+impl TryFrom<VehicleCountDefinition> for VehicleCount {
+  type Error = String;
+
+  fn try_from(definition: VehicleCountDefinition) -> Result<Self, Self::Error> {
+    match definition {
+      VehicleCountDefinition::Count(count) => Ok(VehicleCount::Limited(count)),
+      VehicleCountDefinition::Name(name) if name == "unlimited" => Ok(VehicleCount::Unlimited),
+      VehicleCountDefinition::Name(name) => Err(format!("Expected a number or \"unlimited\" as count, found \"{}\"", name)),
+    }
+  }
+}
+
+impl From<VehicleCount> for VehicleCountDefinition {
+  fn from(count: VehicleCount) -> Self {
+    match count {
+      VehicleCount::Limited(count) => VehicleCountDefinition::Count(count),
+      VehicleCount::Unlimited => VehicleCountDefinition::Name("unlimited".to_string()),
+    }
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VehicleDefinition {
-  pub count: i32,
-  pub capacity: f64,
+  pub count: VehicleCount,
+  pub capacity: Load,
   pub fixed_cost: Cost,
   pub variable_cost: Cost,
+  /* travel time of an arc is its distance divided by speed_factor */
+  #[serde(default = "default_factor")]
+  pub speed_factor: f64,
+  /* service time of a client is multiplied by service_time_factor */
+  #[serde(default = "default_factor")]
+  pub service_time_factor: f64,
+  /* tags like refrigerated or tail-lift matched against Client.required_skills */
+  #[serde(default)]
+  pub skills: Vec<String>,
+  /* times the vehicle can leave the source to serve clients */
+  #[serde(default = "default_max_trips")]
+  pub max_trips: usize,
+  /* time spent at the source between trips */
+  #[serde(default)]
+  pub reload_time: Time,
+  /* the vehicle can't leave the source before shift_start and must be back by shift_end */
+  #[serde(default)]
+  pub shift_start: Option<Time>,
+  #[serde(default)]
+  pub shift_end: Option<Time>,
+  /* continuous driving time allowed before the driver takes a break */
+  #[serde(default)]
+  pub max_driving_time: Option<Time>,
+  /* the driver must take a break starting within the window unless the route ends before */
+  #[serde(default)]
+  pub break_window: Option<[Time; 2]>,
+  #[serde(default)]
+  pub break_duration: Time,
+  /* cost per unit of time the vehicle waits for a time window to open */
+  #[serde(default)]
+  pub wait_cost: Cost,
+  /* time allowed between leaving the source and serving each client */
+  #[serde(default)]
+  pub max_ride_time: Option<Time>,
+}
+
+fn default_factor() -> f64 {
+  1.0
+}
+
+fn default_max_trips() -> usize {
+  1
+}
+
+///
+/// Speed multipliers by time bucket: speeds[k] applies from times[k] until times[k + 1],
+/// the last one applies from then on and the first one before times[0] too.
+/// Travel times are computed by moving through the buckets (Ichoua, Gendreau and Potvin model),
+/// which makes them piecewise linear on the departure time and FIFO consistent.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct SpeedProfile {
+  pub times: Vec<Time>,
+  pub speeds: Vec<f64>,
+}
+
+impl SpeedProfile {
+  ///
+  /// Time it takes to go over distance departing at departure_time with the given base speed.
+  pub fn travel_time(&self, distance: f64, speed: f64, departure_time: Time) -> Time {
+    let mut bucket = self.times.iter().take_while(|time| **time <= departure_time).count().max(1) - 1;
+    let mut distance_left = distance;
+    let mut current_time = departure_time;
+
+    loop {
+      let bucket_speed = speed * self.speeds[bucket];
+
+      if bucket + 1 < self.times.len() {
+        let bucket_end = self.times[bucket + 1];
+        let bucket_distance = (bucket_end - current_time) * bucket_speed;
+
+        if bucket_distance < distance_left {
+          distance_left -= bucket_distance;
+          current_time = bucket_end;
+          bucket += 1;
+          continue
+        }
+      }
+
+      return current_time + distance_left / bucket_speed - departure_time
+    }
+  }
+}
+
+///
+/// Linehaul clients receive their demand from the source, backhaul ones send it back to the source.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientKind {
+  #[default]
+  Linehaul,
+  Backhaul,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -27,12 +264,29 @@ pub struct Client {
   /* id is the index */
   #[serde(skip_deserializing)]
   pub id: usize,
-  pub demand: f64,
+  pub demand: Load,
   pub service_time: Time,
   pub earliest: Time,
   pub latest: Time,
-  /* pos is not actually used but needed to draw the result from the output */
+  /* used by the sweep construction and to draw the result from the output */
   pub pos: [f64; 2],
+  /* skills the vehicle must have to serve the client */
+  #[serde(default)]
+  pub required_skills: Vec<String>,
+  /* vehicle definition indexes that can't serve the client */
+  #[serde(default)]
+  pub forbidden_vehicle_types: Vec<usize>,
+  /* when set the client is optional and leaving it unserved costs the prize */
+  #[serde(default)]
+  pub prize: Option<Cost>,
+  #[serde(default)]
+  pub kind: ClientKind,
+  /* optional clients are never left unserved in favour of lower priority ones */
+  #[serde(default)]
+  pub priority: u32,
+  /* time allowed between leaving the source and serving the client */
+  #[serde(default)]
+  pub max_ride_time: Option<Time>,
 }
 
 #[derive(Debug, Default, Clone, Serialize)]
@@ -40,7 +294,23 @@ pub struct RouteEntryClient {
   pub client_id: usize,
   pub arrive_time: Time,
   pub leave_time: Time,
-  pub wait_time: Time
+  pub wait_time: Time,
+  /* time the service started before the client earliest */
+  pub early_time: Time,
+  /* part of the client demand delivered on this visit, or picked up for backhauls,
+   * all of it unless the delivery is split */
+  pub delivered: Load,
+  /* break stops are placed on the client where the driver rests */
+  pub is_break: bool,
+  /* continuous driving time since the last break */
+  #[serde(skip)]
+  pub driving_time: Time,
+  /* whether the break of the vehicle break_window was taken */
+  #[serde(skip)]
+  pub break_taken: bool,
+  /* time the vehicle left the source on the current trip */
+  #[serde(skip)]
+  pub trip_start: Time,
 }
 
 #[derive(Debug, Default, Clone, Serialize)]
@@ -50,12 +320,38 @@ pub struct RouteEntry {
   pub route_time: Time,
   pub route_fixed_cost: Cost,
   pub route_variable_cost: Cost,
-  pub demand: f64,
+  /* cost of waiting for time windows to open */
+  pub route_wait_cost: Cost,
+  /* penalty of the clients served before their earliest */
+  pub route_early_cost: Cost,
+  /* time from leaving the source until getting back */
+  pub route_duration: Time,
+  /* time served after the latest of the clients */
+  pub route_lateness: Time,
+  /* weighted sum of the objective components */
+  pub route_value: Cost,
+  pub demand: Load,
+  /* demand over vehicle capacity for each dimension, above 1 when there are several trips */
+  pub utilization: Vec<f64>,
+  /* clients holds every trip, each one ends at the source */
+  pub trips: usize,
+  /* load over the vehicle capacity, only on relaxed routes */
+  #[serde(skip)]
+  pub capacity_excess: f64,
+  /* time windows, shift, break and ride time exceeded, only on relaxed routes */
+  #[serde(skip)]
+  pub time_excess: Time,
 }
 
 impl RouteEntry {
   pub fn route_cost(&self) -> Cost {
-    self.route_fixed_cost + self.route_variable_cost
+    self.route_value
+  }
+
+  ///
+  /// Visits of the route, without break stops.
+  pub fn visits(&self) -> Vec<RouteEntryClient> {
+    self.clients.iter().filter(|rc| !rc.is_break).cloned().collect()
   }
 }
 
@@ -65,17 +361,62 @@ impl fmt::Display for RouteEntry {
       f,
 "    - vehicle_id: {}
       route: {}
+      trips: {}
       demand: {}
+      utilization: {}
       route time: {}
       fixed cost: {}
-      variable cost: {}",
+      variable cost: {}
+      wait cost: {}
+      early cost: {}
+      duration: {}
+      lateness: {}
+      value: {}",
       self.vehicle_id,
-      self.clients.iter().map(|client| client.client_id.to_string()).collect::<Vec<String>>().join(", "),
+      self.clients.iter().map(|client| {
+        if client.is_break { "break".to_string() } else { client.client_id.to_string() }
+      }).collect::<Vec<String>>().join(", "),
+      self.trips,
       self.demand,
+      self.utilization.iter().map(|value| format!("{:.2}", value)).collect::<Vec<String>>().join("/"),
       self.route_time,
       self.route_fixed_cost,
       self.route_variable_cost,
+      self.route_wait_cost,
+      self.route_early_cost,
+      self.route_duration,
+      self.route_lateness,
+      self.route_value,
     )
   }
 }
 
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn vehicle_definition_requires_count_and_capacity() {
+    let vehicle_def: VehicleDefinition = serde_json::from_str(
+      r#"{ "count": 2, "capacity": 100, "fixed_cost": 30, "variable_cost": 1 }"#
+    ).unwrap();
+
+    assert_eq!(vehicle_def.speed_factor, 1.0);
+    assert_eq!(vehicle_def.service_time_factor, 1.0);
+    assert_eq!(vehicle_def.max_trips, 1);
+
+    assert!(serde_json::from_str::<VehicleDefinition>(r#"{ "capacity": 100, "fixed_cost": 30, "variable_cost": 1 }"#).is_err());
+    assert!(serde_json::from_str::<VehicleDefinition>(r#"{ "count": 2, "fixed_cost": 30, "variable_cost": 1 }"#).is_err());
+  }
+
+  #[test]
+  fn speed_profile_travel_time_crosses_buckets() {
+    let profile = SpeedProfile { times: vec![0.0, 100.0, 200.0], speeds: vec![1.0, 0.5, 1.0] };
+
+    assert_eq!(profile.travel_time(100.0, 1.0, 50.0), 150.0);
+    assert_eq!(profile.travel_time(100.0, 1.0, 250.0), 100.0);
+    /* Departing later never arrives earlier */
+    assert!(60.0 + profile.travel_time(100.0, 1.0, 60.0) > 50.0 + profile.travel_time(100.0, 1.0, 50.0));
+  }
+}
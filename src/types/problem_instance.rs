@@ -1,8 +1,11 @@
+use std::collections::HashSet;
 use std::fmt;
 
 use serde::{Serialize, Deserialize};
 use crate::utils::time_max;
-use super::{Vehicle, VehicleDefinition, Client, Solution, Time, Cost, RouteEntry, RouteEntryClient};
+use super::{
+  Objective, Vehicle, VehicleCount, VehicleDefinition, Client, ClientKind, Load, ServiceLevel, Solution, SpeedProfile, Time, Cost, RouteEntry, RouteEntryClient,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(default)]
@@ -11,11 +14,37 @@ pub struct ProblemInstance {
   pub source: usize,
   pub deviation_penalty: f64,
   pub allowed_deviation: f64,
+  /* fraction of the time window a client can be served before its earliest */
+  pub allowed_early_deviation: f64,
+  /* cost per unit of time a client is served before its earliest */
+  pub early_deviation_penalty: f64,
+  /* clients whose demand exceeds the vehicle capacity can be served by several visits */
+  pub split_deliveries: bool,
+  /* backhauls can be visited before linehauls of the same trip, load is checked on every visit */
+  pub mixed_backhauls: bool,
   pub distances:  Vec<Vec<Time>>,
+  /* when empty, travel times don't depend on the departure time */
+  pub speed_profiles: Vec<SpeedProfile>,
+  /* profile index of each arc, when empty every arc uses the first profile */
+  pub arc_speed_profiles: Vec<Vec<usize>>,
   pub vehicle_definitions: Vec<VehicleDefinition>,
   #[serde(skip_deserializing)]
   pub vehicles: Vec<Vehicle>,
   pub clients: Vec<Client>,
+  /* weights of the objective components, taken from the configuration */
+  #[serde(skip_deserializing)]
+  pub objective: Objective,
+  /* whether each vehicle type can serve each client */
+  #[serde(skip)]
+  compatibilities: Vec<Vec<bool>>,
+  /* vehicles of limited definitions, followed in vehicles by the first one of each unlimited definition */
+  #[serde(skip)]
+  limited_vehicles: usize,
+  #[serde(skip)]
+  unlimited_types: usize,
+  /* priority of the least critical clients, the only ones left unserved to save cost */
+  #[serde(skip)]
+  lowest_priority: u32,
   #[serde(skip)]
   inited: bool,
 }
@@ -27,61 +56,217 @@ impl Default for ProblemInstance {
       source: 0,
       deviation_penalty: 0.0,
       allowed_deviation: 0.0,
+      allowed_early_deviation: 0.0,
+      early_deviation_penalty: 0.0,
+      split_deliveries: false,
+      mixed_backhauls: false,
       distances: vec![],
+      speed_profiles: vec![],
+      arc_speed_profiles: vec![],
       vehicle_definitions: vec![],
       vehicles: vec![],
       clients: vec![],
+      objective: Default::default(),
+      compatibilities: vec![],
+      limited_vehicles: 0,
+      unlimited_types: 0,
+      lowest_priority: 0,
       inited: false,
     }
   }
 }
 
 impl ProblemInstance {
-  pub fn init(&mut self, optimize_cost: bool) {
+  pub fn init(&mut self, objective: Objective) {
     if self.inited {
       return
     }
 
-    self.init_vehicles(optimize_cost);
+    self.objective = objective;
+    self.init_vehicles();
     self.init_clients();
+    self.init_compatibilities();
 
     self.inited = true;
   }
 
-  /**
-   * Initializes vehicles, if optimize cost is false
-   * then optimization is over distance, this means
-   * that fixed cost is zero and variable cost is 1.
-   */
-  fn init_vehicles(&mut self, optimize_cost: bool) {
-    let mut max: usize = 0;
-
-    let vehicles: Vec<Vehicle> = self.vehicle_definitions.iter().flat_map(|vehicle_def| {
-      let min = max;
-      max = max + vehicle_def.count as usize;
-      (min..max).map(move |id| {
-        Vehicle {
-          id: id,
-          capacity: vehicle_def.capacity,
-          fixed_cost: {
-            if optimize_cost { vehicle_def.fixed_cost }
-            else { 1.0 }
-          },
-          variable_cost: {
-            if optimize_cost { vehicle_def.variable_cost }
-            else { 1.0 }
-          }
-        }
-      })
-    }).collect();
+  fn init_vehicles(&mut self) {
+    /* Vehicles of the limited definitions, then the first vehicle of each unlimited one */
+    let limited = self.vehicle_definitions.iter().enumerate().filter_map(|(vehicle_type, vehicle_def)| {
+      match vehicle_def.count {
+        VehicleCount::Limited(count) => Some((vehicle_type, count)),
+        VehicleCount::Unlimited => None,
+      }
+    });
+    let unlimited = self.vehicle_definitions.iter().enumerate()
+      .filter(|(_, vehicle_def)| vehicle_def.count == VehicleCount::Unlimited)
+      .map(|(vehicle_type, _)| (vehicle_type, 1));
+    let counts: Vec<(usize, usize)> = limited.chain(unlimited).collect();
+
+    self.unlimited_types = counts.iter().filter(|(vehicle_type, _)| {
+      self.vehicle_definitions[*vehicle_type].count == VehicleCount::Unlimited
+    }).count();
+    self.limited_vehicles = counts.iter().map(|(_, count)| count).sum::<usize>() - self.unlimited_types;
+
+    self.vehicles = counts.iter()
+      .flat_map(|(vehicle_type, count)| std::iter::repeat_n(*vehicle_type, *count))
+      .enumerate()
+      .map(|(id, vehicle_type)| self.create_vehicle(id, vehicle_type))
+      .collect();
+  }
+
+  fn create_vehicle(&self, id: usize, vehicle_type: usize) -> Vehicle {
+    let vehicle_def = &self.vehicle_definitions[vehicle_type];
+
+    Vehicle {
+      id,
+      capacity: vehicle_def.capacity.clone(),
+      fixed_cost: vehicle_def.fixed_cost,
+      variable_cost: vehicle_def.variable_cost,
+      speed_factor: vehicle_def.speed_factor,
+      service_time_factor: vehicle_def.service_time_factor,
+      vehicle_type,
+      skills: vehicle_def.skills.clone(),
+      max_trips: vehicle_def.max_trips,
+      reload_time: vehicle_def.reload_time,
+      shift_start: vehicle_def.shift_start,
+      shift_end: vehicle_def.shift_end,
+      max_driving_time: vehicle_def.max_driving_time,
+      break_window: vehicle_def.break_window,
+      break_duration: vehicle_def.break_duration,
+      wait_cost: vehicle_def.wait_cost,
+      max_ride_time: vehicle_def.max_ride_time,
+    }
+  }
+
+  ///
+  /// Vehicle of vehicle_id. Unlimited definitions start with a single vehicle, the next ones are created
+  /// on demand by next_vehicle and share the first one.
+  pub fn vehicle(&self, vehicle_id: usize) -> &Vehicle {
+    let first_created = self.limited_vehicles + self.unlimited_types;
+
+    if vehicle_id < first_created {
+      &self.vehicles[vehicle_id]
+    } else {
+      &self.vehicles[self.limited_vehicles + (vehicle_id - self.limited_vehicles) % self.unlimited_types]
+    }
+  }
+
+  ///
+  /// Vehicle created to replace vehicle_id as the idle one of its unlimited definition once it gets a route,
+  /// None for vehicles of limited definitions.
+  pub fn next_vehicle(&self, vehicle_id: usize) -> Option<usize> {
+    if vehicle_id < self.limited_vehicles {
+      None
+    } else {
+      Some(vehicle_id + self.unlimited_types)
+    }
+  }
+
+  ///
+  /// Vehicles that may start a route besides the used ones: the limited vehicles not used, and the first
+  /// vehicle not used of each unlimited definition.
+  pub fn idle_vehicles(&self, used: &HashSet<usize>) -> Vec<usize> {
+    let limited = (0..self.limited_vehicles).filter(|vehicle_id| !used.contains(vehicle_id));
+    let unlimited = (self.limited_vehicles..self.limited_vehicles + self.unlimited_types).map(|mut vehicle_id| {
+      while used.contains(&vehicle_id) {
+        vehicle_id += self.unlimited_types;
+      }
+      vehicle_id
+    });
+
+    limited.chain(unlimited).collect()
+  }
+
+  ///
+  /// One idle vehicle of each vehicle type that has idle vehicles left.
+  pub fn idle_vehicle_per_type(&self, used: &HashSet<usize>) -> Vec<usize> {
+    let mut vehicle_types: HashSet<usize> = HashSet::new();
+
+    self.idle_vehicles(used).into_iter()
+      .filter(|vehicle_id| vehicle_types.insert(self.vehicle(*vehicle_id).vehicle_type))
+      .collect()
+  }
+
+  ///
+  /// Number of idle vehicles of each vehicle type.
+  pub fn idle_count(&self, used: &HashSet<usize>) -> Vec<usize> {
+    let mut idle_count = vec![0; self.vehicle_definitions.len()];
+
+    /* Unlimited vehicle types never run out of idle vehicles */
+    for vehicle_id in self.idle_vehicles(used) {
+      let vehicle_type = self.vehicle(vehicle_id).vehicle_type;
+      idle_count[vehicle_type] = match self.next_vehicle(vehicle_id) {
+        Some(_) => usize::MAX,
+        None => idle_count[vehicle_type] + 1,
+      };
+    }
+
+    idle_count
+  }
+
+  ///
+  /// Empty routes of one vehicle per vehicle type not used by routes.
+  pub fn unused_vehicle_routes(&self, routes: &[RouteEntry]) -> Vec<RouteEntry> {
+    let used_vehicles: HashSet<usize> = routes.iter().map(|route| route.vehicle_id).collect();
 
-    self.vehicles = vehicles;
+    self.idle_vehicle_per_type(&used_vehicles).into_iter()
+      .map(|vehicle_id| RouteEntry { vehicle_id, ..Default::default() })
+      .collect()
+  }
+
+  ///
+  /// Vehicles of each definition, of unlimited ones as many as clients, which are created on demand.
+  pub fn type_vehicles(&self) -> Vec<Vec<usize>> {
+    let mut type_vehicles: Vec<Vec<usize>> = vec![vec![]; self.vehicle_definitions.len()];
+
+    for vehicle in self.vehicles.iter() {
+      match self.next_vehicle(vehicle.id) {
+        Some(_) => type_vehicles[vehicle.vehicle_type].extend(
+          (0..self.clients.len().saturating_sub(1)).map(|index| vehicle.id + index * self.unlimited_types)
+        ),
+        None => type_vehicles[vehicle.vehicle_type].push(vehicle.id),
+      }
+    }
+
+    type_vehicles
+  }
+
+  ///
+  /// Adds the vehicles created on demand among vehicle_ids to vehicles, so the output lists every vehicle
+  /// its routes refer to.
+  pub fn add_created_vehicles(&mut self, vehicle_ids: impl Iterator<Item = usize>) {
+    let first_created = self.limited_vehicles + self.unlimited_types;
+    let mut created: Vec<usize> = vehicle_ids.filter(|vehicle_id| *vehicle_id >= first_created).collect();
+    created.sort_unstable();
+    created.dedup();
+
+    for vehicle_id in created {
+      let vehicle = self.create_vehicle(vehicle_id, self.vehicle(vehicle_id).vehicle_type);
+      self.vehicles.push(vehicle);
+    }
   }
 
   fn init_clients(&mut self) {
     for index in 0..self.clients.len() {
       self.clients[index].id = index;
     }
+
+    self.lowest_priority = self.clients.iter()
+      .filter(|client| client.id != self.source)
+      .map(|client| client.priority)
+      .min()
+      .unwrap_or(0);
+  }
+
+  fn init_compatibilities(&mut self) {
+    self.compatibilities = self.vehicle_definitions.iter().enumerate().map(|(vehicle_type, vehicle_def)| {
+      self.clients.iter().map(|client| {
+        !client.forbidden_vehicle_types.contains(&vehicle_type)
+        &&
+        client.required_skills.iter().all(|skill| vehicle_def.skills.contains(skill))
+      }).collect()
+    }).collect();
   }
 
   pub fn validate(&self) -> Result<(), String> {
@@ -93,43 +278,424 @@ impl ProblemInstance {
       }
     }
 
-    if self.vehicles.len() == 0 {
+    if self.vehicles.is_empty() {
       return Err("You must specify at least one vehicle".to_string());
     }
 
-    if self.clients.len() == 0 {
+    if self.clients.is_empty() {
       return Err("You must specify some clients".to_string());
     }
 
+    if self.allowed_early_deviation < 0.0 || self.early_deviation_penalty < 0.0 {
+      return Err("Expected non negative allowed_early_deviation and early_deviation_penalty".to_string());
+    }
+
+    for (index, profile) in self.speed_profiles.iter().enumerate() {
+      if profile.times.is_empty() || profile.times.len() != profile.speeds.len() {
+        return Err(format!("Expected the same non zero amount of times and speeds on speed profile {}", index));
+      }
+
+      if profile.times.windows(2).any(|times| times[0] >= times[1]) {
+        return Err(format!("Expected increasing times on speed profile {}", index));
+      }
+
+      if profile.speeds.iter().any(|speed| *speed <= 0.0) {
+        return Err(format!("Expected positive speeds on speed profile {}", index));
+      }
+    }
+
+    if !self.arc_speed_profiles.is_empty() {
+      if self.arc_speed_profiles.len() != node_count {
+        return Err(format!("Expected {} arc speed profile vectors", node_count));
+      }
+
+      for (index, profiles) in self.arc_speed_profiles.iter().enumerate() {
+        if profiles.len() != node_count {
+          return Err(format!("Expected arc speed profile vector of {} on index {}", node_count, index));
+        }
+
+        if profiles.iter().any(|profile| *profile >= self.speed_profiles.len()) {
+          return Err(format!("Unknown speed profile on arc speed profile vector {}", index));
+        }
+      }
+    }
+
+    let dimension = self.load_dimension();
+
+    if dimension == 0 {
+      return Err("Expected capacities of at least one dimension".to_string());
+    }
+
+    for (index, vehicle_def) in self.vehicle_definitions.iter().enumerate() {
+      if vehicle_def.capacity.dimension() != dimension {
+        return Err(format!("Expected capacity of dimension {} on vehicle definition {}", dimension, index));
+      }
+    }
+
+    for client in self.clients.iter() {
+      if client.demand.dimension() != dimension {
+        return Err(format!("Expected demand of dimension {} on client {}", dimension, client.id));
+      }
+
+      if client.max_ride_time.is_some_and(|max_ride_time| max_ride_time <= 0 as Time) {
+        return Err(format!("Expected positive max_ride_time on client {}", client.id));
+      }
+
+      if client.forbidden_vehicle_types.iter().any(|vehicle_type| *vehicle_type >= self.vehicle_definitions.len()) {
+        return Err(format!("Unknown forbidden vehicle type on client {}", client.id));
+      }
+
+      if !self.compatibilities.iter().any(|compatibilities| compatibilities[client.id]) {
+        return Err(format!("No vehicle type can serve client {}", client.id));
+      }
+
+      let fits_in_vehicle = self.vehicle_definitions.iter().enumerate().any(|(vehicle_type, vehicle_def)| {
+        self.compatibilities[vehicle_type][client.id] && client.demand.fits_in(&vehicle_def.capacity)
+      });
+      if !self.split_deliveries && !fits_in_vehicle {
+        return Err(format!("Client {} demand exceeds every vehicle capacity, enable split_deliveries", client.id));
+      }
+    }
+
+    for (index, vehicle_def) in self.vehicle_definitions.iter().enumerate() {
+      if vehicle_def.speed_factor <= 0.0 {
+        return Err(format!("Expected positive speed_factor on vehicle definition {}", index));
+      }
+
+      if vehicle_def.service_time_factor < 0.0 {
+        return Err(format!("Expected non negative service_time_factor on vehicle definition {}", index));
+      }
+
+      if vehicle_def.wait_cost < 0 as Cost {
+        return Err(format!("Expected non negative wait_cost on vehicle definition {}", index));
+      }
+
+      if vehicle_def.max_ride_time.is_some_and(|max_ride_time| max_ride_time <= 0 as Time) {
+        return Err(format!("Expected positive max_ride_time on vehicle definition {}", index));
+      }
+
+      if vehicle_def.max_trips == 0 {
+        return Err(format!("Expected at least one trip on vehicle definition {}", index));
+      }
+
+      if let (Some(shift_start), Some(shift_end)) = (vehicle_def.shift_start, vehicle_def.shift_end) {
+        if shift_start > shift_end {
+          return Err(format!("Expected shift_start before shift_end on vehicle definition {}", index));
+        }
+      }
+
+      if vehicle_def.max_driving_time.is_some_and(|max_driving_time| max_driving_time <= 0 as Time) {
+        return Err(format!("Expected positive max_driving_time on vehicle definition {}", index));
+      }
+
+      if let Some([earliest, latest]) = vehicle_def.break_window {
+        if earliest > latest {
+          return Err(format!("Expected increasing break_window on vehicle definition {}", index));
+        }
+      }
+
+      let has_breaks = vehicle_def.max_driving_time.is_some() || vehicle_def.break_window.is_some();
+      if has_breaks && vehicle_def.break_duration <= 0 as Time {
+        return Err(format!("Expected positive break_duration on vehicle definition {}", index));
+      }
+    }
+
+    /* Drivers leaving the source after their break window could never take the break */
+    for vehicle in self.vehicles.iter() {
+      if vehicle.break_window.is_some_and(|[_, latest]| self.route_start(vehicle.id).leave_time > latest) {
+        return Err(format!("Expected break_window ending after the shift starts on vehicle definition {}", vehicle.vehicle_type));
+      }
+    }
+
     Ok(())
   }
 
   ///
-  /// Creates a route entry from the following params:
-  /// - arc_time: Time to go to the client client_to_id.
-  /// - client_to_id: id of the route entry client.
-  /// - current_time: time of departure.
-  pub fn create_route_entry_client(&self, arc_time: Time, client_to_id: usize, current_time: Time) -> RouteEntryClient {
-    let client_to = &self.clients[client_to_id];
-    let arrive_time = time_max(current_time + arc_time, client_to.earliest);
-    let wait_time = time_max(0 as Time, client_to.earliest - current_time - arc_time);
-    let leave_time = arrive_time + client_to.service_time;
+  /// Number of dimensions of capacities and demands.
+  pub fn load_dimension(&self) -> usize {
+    self.vehicles.first().map(|vehicle| vehicle.capacity.dimension()).unwrap_or(0)
+  }
+
+  ///
+  /// Whether vehicle_id has the skills and type required by client_id.
+  pub fn can_serve(&self, vehicle_id: usize, client_id: usize) -> bool {
+    self.compatibilities[self.vehicle(vehicle_id).vehicle_type][client_id]
+  }
+
+  ///
+  /// Time it takes vehicle_id to go from client_from_id to client_to_id departing at departure_time.
+  pub fn travel_time(
+    &self, vehicle_id: usize, client_from_id: usize, client_to_id: usize, departure_time: Time,
+  ) -> Time {
+    let distance = self.distances[client_from_id][client_to_id];
+    let speed = self.vehicle(vehicle_id).speed_factor;
+
+    if self.speed_profiles.is_empty() {
+      return distance / speed
+    }
+
+    let profile_index = if self.arc_speed_profiles.is_empty() {
+      0
+    } else {
+      self.arc_speed_profiles[client_from_id][client_to_id]
+    };
 
+    self.speed_profiles[profile_index].travel_time(distance, speed, departure_time)
+  }
+
+  ///
+  /// Time it takes vehicle_id to serve client_id.
+  pub fn service_time(&self, vehicle_id: usize, client_id: usize) -> Time {
+    self.clients[client_id].service_time * self.vehicle(vehicle_id).service_time_factor
+  }
+
+  ///
+  /// Time it takes vehicle_id to reload when arriving at client_id, only the source takes time.
+  pub fn reload_time(&self, vehicle_id: usize, client_id: usize) -> Time {
+    if client_id == self.source {
+      self.vehicle(vehicle_id).reload_time
+    } else {
+      0 as Time
+    }
+  }
+
+  ///
+  /// Earliest time vehicle_id can start serving client_id. Clients are served early
+  /// only when the early deviation penalty is cheaper than the vehicle waiting.
+  pub fn service_earliest(&self, vehicle_id: usize, client_id: usize) -> Time {
+    let client = &self.clients[client_id];
+
+    if self.objective.early * self.early_deviation_penalty < self.vehicle(vehicle_id).wait_cost * self.objective.waiting {
+      client.earliest - self.allowed_early_deviation * (client.latest - client.earliest)
+    } else {
+      client.earliest
+    }
+  }
+
+  ///
+  /// Visit to client_id delivering all its demand, used as a template to build routes.
+  pub fn visit(&self, client_id: usize) -> RouteEntryClient {
     RouteEntryClient {
+      client_id,
+      delivered: self.clients[client_id].demand.clone(),
+      ..Default::default()
+    }
+  }
+
+  ///
+  /// Part of the demand left of a client vehicle_id delivers on a visit, a full load
+  /// when it exceeds the vehicle capacity and deliveries are split.
+  pub fn split_delivery(&self, vehicle_id: usize, demand_left: &Load) -> Load {
+    let capacity = &self.vehicle(vehicle_id).capacity;
+
+    if self.split_deliveries && !demand_left.fits_in(capacity) {
+      demand_left.min(capacity)
+    } else {
+      demand_left.clone()
+    }
+  }
+
+  ///
+  /// Creates the route entry of vehicle_id at the source when its shift starts.
+  pub fn route_start(&self, vehicle_id: usize) -> RouteEntryClient {
+    let vehicle = self.vehicle(vehicle_id);
+    let source = &self.clients[self.source];
+    let arrive_time = time_max(source.earliest, vehicle.shift_start.unwrap_or(source.earliest));
+
+    RouteEntryClient {
+      client_id: self.source,
+      arrive_time,
+      leave_time: arrive_time + self.service_time(vehicle_id, self.source),
+      delivered: source.demand.clone(),
+      ..Default::default()
+    }
+  }
+
+  ///
+  /// Whether the driver of vehicle_id must rest at route_client before driving arc_time departing at departure_time,
+  /// to be done with the next client at busy_until.
+  fn needs_break(
+    &self, vehicle_id: usize, route_client: &RouteEntryClient, departure_time: Time, arc_time: Time, busy_until: Time,
+  ) -> bool {
+    let vehicle = self.vehicle(vehicle_id);
+
+    let driving_exceeded = vehicle.max_driving_time.is_some_and(|max_driving_time| {
+      route_client.driving_time > 0 as Time && route_client.driving_time + arc_time > max_driving_time
+    });
+
+    /* Breaks are taken at the first departure within the window, or before driving or serving past it */
+    let window_reached = vehicle.break_window.is_some_and(|[earliest, latest]| {
+      !route_client.break_taken
+      && departure_time <= latest
+      && (departure_time >= earliest || busy_until > latest)
+    });
+
+    driving_exceeded || window_reached
+  }
+
+  ///
+  /// Break stop of the driver at the route client from, from break_start to break_end, ready to rest since ready_time.
+  fn break_stop(from: &RouteEntryClient, ready_time: Time, break_start: Time, break_end: Time, break_taken: bool) -> RouteEntryClient {
+    RouteEntryClient {
+      client_id: from.client_id,
+      arrive_time: break_start,
+      leave_time: break_end,
+      wait_time: break_start - ready_time,
+      is_break: true,
+      driving_time: 0 as Time,
+      early_time: 0 as Time,
+      delivered: Load::default(),
+      break_taken,
+      trip_start: from.trip_start,
+    }
+  }
+
+  ///
+  /// Plans the move of vehicle_id from the route client from to client_to_id.
+  /// Returns the break stop the driver takes before leaving, if needed, and the route entry of client_to_id.
+  pub fn plan_move(
+    &self, vehicle_id: usize, from: &RouteEntryClient, client_to_id: usize,
+  ) -> (Option<RouteEntryClient>, RouteEntryClient) {
+    let vehicle = self.vehicle(vehicle_id);
+    let client_to = &self.clients[client_to_id];
+    let mut current_time = from.leave_time;
+    let mut driving_time = from.driving_time;
+    let mut break_taken = from.break_taken;
+    let mut break_stop = None;
+
+    let service_earliest = self.service_earliest(vehicle_id, client_to_id);
+    let service_time = self.service_time(vehicle_id, client_to_id);
+    let busy_until = |departure: Time, arc_time: Time| time_max(departure + arc_time, service_earliest) + service_time;
+
+    let mut arc_time = self.travel_time(vehicle_id, from.client_id, client_to_id, current_time);
+    if self.needs_break(vehicle_id, from, current_time, arc_time, busy_until(current_time, arc_time)) {
+      let break_start = match vehicle.break_window {
+        Some([earliest, latest]) if !break_taken => {
+          let start = time_max(current_time, earliest);
+          break_taken = start <= latest;
+          start
+        },
+        _ => current_time,
+      };
+
+      let break_end = break_start + vehicle.break_duration;
+      break_stop = Some(Self::break_stop(from, current_time, break_start, break_end, break_taken));
+
+      current_time = break_end;
+      driving_time = 0 as Time;
+      arc_time = self.travel_time(vehicle_id, from.client_id, client_to_id, current_time);
+    }
+
+    /* Ride times are kept short by leaving the source as late as the next time window allows */
+    let has_ride_limit = client_to.max_ride_time.is_some() || vehicle.max_ride_time.is_some();
+    if from.client_id == self.source && has_ride_limit {
+      let departure = service_earliest - arc_time;
+
+      if departure > current_time {
+        current_time = departure;
+        arc_time = self.travel_time(vehicle_id, from.client_id, client_to_id, current_time);
+      }
+    }
+
+    /* A departure postponed into or past the break window leaves the driver resting at the source before */
+    let source_break = vehicle.break_window.filter(|[earliest, latest]| {
+      from.client_id == self.source && break_stop.is_none() && !break_taken && from.leave_time <= *latest
+      && (current_time >= *earliest || busy_until(current_time, arc_time) > *latest)
+    });
+    if let Some([earliest, _]) = source_break {
+      let break_start = time_max(from.leave_time, earliest);
+      let break_end = break_start + vehicle.break_duration;
+      break_taken = true;
+      break_stop = Some(Self::break_stop(from, from.leave_time, break_start, break_end, break_taken));
+
+      driving_time = 0 as Time;
+      if break_end > current_time {
+        current_time = break_end;
+        arc_time = self.travel_time(vehicle_id, from.client_id, client_to_id, current_time);
+      }
+    }
+
+    let trip_start = if from.client_id == self.source { current_time } else { from.trip_start };
+
+    let arrive_time = time_max(current_time + arc_time, service_earliest);
+    let wait_time = time_max(0 as Time, service_earliest - current_time - arc_time);
+    let early_time = time_max(0 as Time, client_to.earliest - arrive_time);
+    let leave_time = arrive_time + service_time + self.reload_time(vehicle_id, client_to_id);
+
+    let route_client = RouteEntryClient {
       client_id: client_to_id,
-      arrive_time: arrive_time,
-      leave_time: leave_time,
-      wait_time: wait_time,
+      arrive_time,
+      leave_time,
+      wait_time,
+      early_time,
+      delivered: client_to.demand.clone(),
+      is_break: false,
+      driving_time: driving_time + arc_time,
+      break_taken,
+      trip_start,
+    };
+
+    (break_stop, route_client)
+  }
+
+  ///
+  /// Creates the route entry of client_to_id when vehicle_id departs from the route client from,
+  /// considering the break the driver may take before.
+  pub fn create_route_entry_client(
+    &self, vehicle_id: usize, from: &RouteEntryClient, client_to_id: usize,
+  ) -> RouteEntryClient {
+    self.plan_move(vehicle_id, from, client_to_id).1
+  }
+
+  ///
+  /// Appends client_to_id to the route clients of vehicle_id, preceded by a break stop if the driver needs one.
+  /// Departures from the source may be postponed, then the stop before leaves at the start of the trip.
+  /// The route clients must not be empty.
+  pub fn append_route_client(&self, vehicle_id: usize, route_clients: &mut Vec<RouteEntryClient>, client_to_id: usize) {
+    let from = route_clients.last().unwrap();
+    let leaves_source = from.client_id == self.source;
+    let (break_stop, route_client) = self.plan_move(vehicle_id, from, client_to_id);
+
+    if let Some(break_stop) = break_stop {
+      route_clients.push(break_stop);
+    }
+    if leaves_source {
+      route_clients.last_mut().unwrap().leave_time = route_client.trip_start;
     }
+    route_clients.push(route_client);
   }
 
+  ///
+  /// Appends the visit to the route clients of vehicle_id keeping the quantity it delivers.
+  pub fn append_route_visit(&self, vehicle_id: usize, route_clients: &mut Vec<RouteEntryClient>, visit: &RouteEntryClient) {
+    self.append_route_client(vehicle_id, route_clients, visit.client_id);
+    route_clients.last_mut().unwrap().delivered = visit.delivered.clone();
+  }
+
+  ///
+  /// Computes the objective components of the route and its value.
+  /// Routes without clients besides the source are left empty, as the vehicle is not used.
   pub fn compute_route_costs(&self, route: &mut RouteEntry) {
-    let vehicle = &self.vehicles[route.vehicle_id];
+    let vehicle = self.vehicle(route.vehicle_id);
+    let objective = &self.objective;
 
     route.route_variable_cost = 0 as Cost;
     route.route_fixed_cost = 0 as Cost;
+    route.route_wait_cost = 0 as Cost;
+    route.route_early_cost = 0 as Cost;
+    route.route_duration = 0 as Time;
+    route.route_lateness = 0 as Time;
+    route.route_value = 0 as Cost;
     route.route_time = 0 as Time;
-    route.demand = 0.0;
+    route.demand = Load::default();
+    route.utilization = vec![];
+    route.trips = 0;
+    route.capacity_excess = 0.0;
+    route.time_excess = 0 as Time;
+
+    if route.clients.iter().all(|route_client| route_client.client_id == self.source) {
+      route.clients.clear();
+    }
 
     if route.clients.is_empty() {
       return
@@ -138,32 +704,327 @@ impl ProblemInstance {
     route.route_fixed_cost = vehicle.fixed_cost;
 
     let mut prev_client_id = route.clients.first().unwrap().client_id;
-    for route_client in route.clients.iter() {
+    for route_client in route.clients.iter().filter(|route_client| !route_client.is_break) {
       let arc_time = self.distances[prev_client_id][route_client.client_id];
 
-      route.demand += self.clients[route_client.client_id].demand;
+      route.demand.add(&route_client.delivered);
+      if route_client.client_id == self.source && prev_client_id != route_client.client_id {
+        route.trips += 1;
+      }
       route.route_time += arc_time;
       route.route_variable_cost += arc_time * vehicle.variable_cost;
       prev_client_id = route_client.client_id;
     }
+
+    for route_client in route.clients.iter() {
+      route.route_wait_cost += route_client.wait_time * vehicle.wait_cost;
+      route.route_early_cost += route_client.early_time * self.early_deviation_penalty;
+
+      if !route_client.is_break {
+        route.route_lateness += time_max(route_client.arrive_time - self.clients[route_client.client_id].latest, 0 as Time);
+      }
+    }
+
+    route.route_duration = route.clients.last().unwrap().arrive_time - route.clients.first().unwrap().leave_time;
+    route.utilization = route.demand.utilization(&vehicle.capacity);
+
+    route.route_value = objective.fixed_cost * route.route_fixed_cost
+      + objective.variable_cost * route.route_variable_cost
+      + objective.distance * route.route_time
+      + objective.duration * route.route_duration
+      + objective.waiting * route.route_wait_cost
+      + objective.lateness * route.route_lateness
+      + objective.early * route.route_early_cost
+      + objective.routes;
+  }
+
+  ///
+  /// Creates the route of vehicle_id making the visits in order, both ends must be the source.
+  /// Returns None if the route is not feasible, and an empty route if there are no clients
+  /// besides the source.
+  pub fn build_route(&self, vehicle_id: usize, visits: &[RouteEntryClient]) -> Option<RouteEntry> {
+    let mut route = RouteEntry { vehicle_id, ..Default::default() };
+
+    if visits.len() <= 2 {
+      self.compute_route_costs(&mut route);
+      return Some(route)
+    }
+
+    if !self.is_load_feasible(vehicle_id, visits.iter()) {
+      return None
+    }
+
+    route.clients.push(self.route_start(vehicle_id));
+
+    for visit in visits[1..].iter() {
+      if !self.can_serve(vehicle_id, visit.client_id) {
+        return None
+      }
+
+      if !self.is_move_feasible(vehicle_id, route.clients.last().unwrap(), visit.client_id) {
+        return None
+      }
+
+      self.append_route_visit(vehicle_id, &mut route.clients, visit);
+    }
+
+    self.compute_route_costs(&mut route);
+
+    Some(route)
+  }
+
+  ///
+  /// Creates the route of vehicle_id making the visits in order like build_route, but the load may exceed the
+  /// vehicle capacity and the time windows, shift, break and ride time limits may be exceeded, which is measured
+  /// on the route capacity_excess and time_excess. Returns None if the vehicle can't serve a client, the number
+  /// of trips exceeds its max_trips or backhauls are visited before linehauls.
+  pub fn build_relaxed_route(&self, vehicle_id: usize, visits: &[RouteEntryClient]) -> Option<RouteEntry> {
+    let mut route = RouteEntry { vehicle_id, ..Default::default() };
+
+    if visits.len() <= 2 {
+      self.compute_route_costs(&mut route);
+      return Some(route)
+    }
+
+    route.clients.push(self.route_start(vehicle_id));
+
+    for visit in visits[1..].iter() {
+      self.append_route_visit(vehicle_id, &mut route.clients, visit);
+    }
+
+    self.compute_route_costs(&mut route);
+    self.compute_route_excess(&mut route)?;
+
+    Some(route)
   }
 
   ///
-  /// Check if a move is feasible
-  pub fn is_move_feasible(&self, client_from_id: usize, client_to_id: usize, current_time: Time) -> bool {
-    let arrival_time = self.distances[client_from_id][client_to_id] + current_time;
+  /// Measures the capacity_excess and time_excess of a relaxed route, after its costs are computed.
+  /// Returns None if the vehicle can't serve a client, the number of trips exceeds its max_trips or
+  /// backhauls are visited before linehauls.
+  pub fn compute_route_excess(&self, route: &mut RouteEntry) -> Option<()> {
+    let vehicle_id = route.vehicle_id;
+    let visits = route.clients.iter().filter(|rc| !rc.is_break);
+
+    if !visits.clone().all(|visit| self.can_serve(vehicle_id, visit.client_id)) {
+      return None
+    }
+
+    let capacity_excess = self.load_excess(vehicle_id, visits.clone())?;
+    let time_excess = visits.clone().zip(visits.skip(1))
+      .map(|(from, to)| self.move_time_excess(vehicle_id, from, to.client_id))
+      .sum();
+
+    route.capacity_excess = capacity_excess;
+    route.time_excess = time_excess;
+
+    Some(())
+  }
+
+  ///
+  /// Check the load of each trip fits vehicle_id capacity and the number of trips doesn't exceed
+  /// the vehicle max_trips. visits must start at the source and have no break stops.
+  pub fn is_load_feasible<'a, I: Iterator<Item = &'a RouteEntryClient>>(&self, vehicle_id: usize, visits: I) -> bool {
+    self.load_excess(vehicle_id, visits) == Some(0.0)
+  }
+
+  ///
+  /// Sum of the greatest load excess over vehicle_id capacity of each trip. Returns None if the number of trips
+  /// exceeds the vehicle max_trips or backhauls are visited before linehauls.
+  /// visits must start at the source and have no break stops.
+  pub fn load_excess<'a, I: Iterator<Item = &'a RouteEntryClient>>(&self, vehicle_id: usize, visits: I) -> Option<f64> {
+    let vehicle = self.vehicle(vehicle_id);
+    let mut trip = vec![];
+    let mut source_visits = 0;
+    let mut excess = 0.0;
+
+    for visit in visits {
+      if visit.client_id == self.source {
+        source_visits += 1;
+        excess += self.trip_load_excess(vehicle_id, &trip)?;
+        trip.clear();
+        continue
+      }
+
+      trip.push(visit);
+    }
+
+    excess += self.trip_load_excess(vehicle_id, &trip)?;
+
+    if source_visits > vehicle.max_trips + 1 {
+      return None
+    }
+
+    Some(excess)
+  }
+
+  ///
+  /// Greatest excess of the load of vehicle_id over its capacity on the visits of the trip. The vehicle leaves
+  /// the source with the linehaul demands and picks up the backhaul ones, which must come after every linehaul
+  /// unless backhauls are mixed, otherwise returns None.
+  fn trip_load_excess(&self, vehicle_id: usize, trip: &[&RouteEntryClient]) -> Option<f64> {
+    let capacity = &self.vehicle(vehicle_id).capacity;
+    let mut load = Load::default();
+
+    for visit in trip.iter().filter(|visit| self.clients[visit.client_id].kind == ClientKind::Linehaul) {
+      load.add(&visit.delivered);
+    }
+
+    let mut excess = load.excess(capacity);
+    let mut backhaul_visited = false;
+    for visit in trip {
+      match self.clients[visit.client_id].kind {
+        ClientKind::Linehaul => {
+          if backhaul_visited && !self.mixed_backhauls {
+            return None
+          }
+          load.sub(&visit.delivered);
+        },
+        ClientKind::Backhaul => {
+          backhaul_visited = true;
+          load.add(&visit.delivered);
+          excess = excess.max(load.excess(capacity));
+        },
+      }
+    }
+
+    Some(excess)
+  }
+
+  ///
+  /// Whether the client priority is above the lowest one, those clients are served whenever it's feasible
+  /// and only the lowest priority ones are left unserved to save cost.
+  pub fn is_prioritized(&self, client_id: usize) -> bool {
+    self.clients[client_id].priority > self.lowest_priority
+  }
+
+  ///
+  /// Order in which the constructions place the clients when some may be left unserved: clients that must
+  /// be served first, then the higher priorities.
+  pub fn service_rank(&self, client_id: usize) -> (bool, u32) {
+    let client = &self.clients[client_id];
+
+    (client.prize.is_none(), client.priority)
+  }
+
+  ///
+  /// Whether some clients can be left unserved.
+  pub fn has_optional_clients(&self) -> bool {
+    self.clients.iter().any(|client| client.prize.is_some())
+  }
+
+  ///
+  /// Keeps the candidates whose client has the highest service rank. Clients that must be served, and then
+  /// higher priority ones, take the vehicles first when some clients may be left unserved.
+  pub fn highest_ranked<T>(&self, candidates: &mut Vec<T>, client_id: impl Fn(&T) -> usize) {
+    if self.has_optional_clients() {
+      let max_rank = candidates.iter().map(|candidate| self.service_rank(client_id(candidate))).max();
+      candidates.retain(|candidate| Some(self.service_rank(client_id(candidate))) == max_rank);
+    }
+  }
+
+  ///
+  /// Check if a move of vehicle_id from the route client from to client_to_id is feasible,
+  /// considering the client time window and max ride time, and the driver shift and break.
+  pub fn is_move_feasible(&self, vehicle_id: usize, from: &RouteEntryClient, client_to_id: usize) -> bool {
+    let vehicle = self.vehicle(vehicle_id);
+    let route_client = self.create_route_entry_client(vehicle_id, from, client_to_id);
+    let arrival_time = route_client.arrive_time - route_client.wait_time;
     let client = &self.clients[client_to_id];
+
+    if client_to_id == self.source && vehicle.shift_end.is_some_and(|shift_end| arrival_time > shift_end) {
+      return false
+    }
+
+    if !route_client.break_taken && vehicle.break_window.is_some_and(|[_, latest]| arrival_time > latest) {
+      return false
+    }
+
+    if client_to_id != self.source {
+      let ride_time = route_client.arrive_time - route_client.trip_start;
+      let ride_time_exceeded = |max_ride_time: Time| ride_time > max_ride_time;
+
+      if client.max_ride_time.is_some_and(ride_time_exceeded) || vehicle.max_ride_time.is_some_and(ride_time_exceeded) {
+        return false
+      }
+    }
     
     arrival_time < client.latest + self.allowed_deviation * (client.latest - client.earliest)
   }
   
+  ///
+  /// Time exceeded on the move of vehicle_id from the route client from to client_to_id: the arrival after the
+  /// client time window allowed deviation, the shift end or the break window, and the ride time over its limits.
+  pub fn move_time_excess(&self, vehicle_id: usize, from: &RouteEntryClient, client_to_id: usize) -> Time {
+    let vehicle = self.vehicle(vehicle_id);
+    let route_client = self.create_route_entry_client(vehicle_id, from, client_to_id);
+    let arrival_time = route_client.arrive_time - route_client.wait_time;
+    let client = &self.clients[client_to_id];
+    let mut excess = 0 as Time;
+
+    if client_to_id == self.source {
+      excess += vehicle.shift_end.map_or(0 as Time, |shift_end| time_max(arrival_time - shift_end, 0 as Time));
+    }
+
+    if !route_client.break_taken {
+      excess += vehicle.break_window.map_or(0 as Time, |[_, latest]| time_max(arrival_time - latest, 0 as Time));
+    }
+
+    if client_to_id != self.source {
+      let ride_time = route_client.arrive_time - route_client.trip_start;
+      let ride_time_excess = |max_ride_time: Time| time_max(ride_time - max_ride_time, 0 as Time);
+
+      excess += client.max_ride_time.map_or(0 as Time, ride_time_excess);
+      excess += vehicle.max_ride_time.map_or(0 as Time, ride_time_excess);
+    }
+
+    excess + time_max(arrival_time - client.latest - self.allowed_deviation * (client.latest - client.earliest), 0 as Time)
+  }
+
+  ///
+  /// Clients of each priority class served, and served on time, by the solution.
+  fn service_levels(&self, sol: &Solution) -> Vec<ServiceLevel> {
+    let mut served = vec![false; self.clients.len()];
+    let mut late = vec![false; self.clients.len()];
+
+    for route_client in sol.routes.iter().flat_map(|route| route.clients.iter()).filter(|rc| !rc.is_break) {
+      served[route_client.client_id] = true;
+      late[route_client.client_id] |= route_client.arrive_time > self.clients[route_client.client_id].latest;
+    }
+
+    let mut priorities: Vec<u32> = self.clients.iter().map(|client| client.priority).collect();
+    priorities.sort_unstable_by(|p1, p2| p2.cmp(p1));
+    priorities.dedup();
+
+    priorities.into_iter().map(|priority| {
+      let clients = self.clients.iter().filter(|client| client.id != self.source && client.priority == priority);
+
+      ServiceLevel {
+        priority,
+        clients: clients.clone().count(),
+        served: clients.clone().filter(|client| served[client.id]).count(),
+        on_time: clients.filter(|client| served[client.id] && !late[client.id]).count(),
+      }
+    }).filter(|level| level.clients > 0).collect()
+  }
+
   ///
   /// Objective calculation
   pub fn evaluate_sol(&self, sol: &mut Solution) {
     let truck_cost = sol.routes.iter().map(|route| route.route_cost()).sum::<Cost>();
 
-    sol.value = truck_cost;
+    sol.unserved_penalty = sol.unserved_clients.iter()
+      .map(|client_id| self.clients[*client_id].prize.unwrap_or(0 as Cost))
+      .fold(0 as Cost, |total, prize| total + prize);
+    sol.value = truck_cost + sol.unserved_penalty;
+    sol.fleet_mix = vec![0; self.vehicle_definitions.len()];
+    for route in sol.routes.iter() {
+      sol.fleet_mix[self.vehicle(route.vehicle_id).vehicle_type] += 1;
+    }
+    sol.service_levels = self.service_levels(sol);
     sol.distance = sol.total_route_time();
+    sol.fixed_cost = sol.routes.iter().map(|route| route.route_fixed_cost).sum();
+    sol.lateness = sol.routes.iter().map(|route| route.route_lateness).sum();
   }
 }
 
@@ -175,12 +1036,108 @@ impl fmt::Display for ProblemInstance {
   vehicles: {}
   nodes: {}
   allowed excess: {}
-  penalty: {}",
+  penalty: {}
+  allowed early excess: {}
+  early penalty: {}",
       self.name,
       self.vehicles.len(),
       self.clients.len(),
       self.allowed_deviation,
       self.deviation_penalty,
+      self.allowed_early_deviation,
+      self.early_deviation_penalty,
     )
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+
+  use serde_json::json;
+
+  use super::super::test_instances::instance;
+  use super::ProblemInstance;
+
+  #[test]
+  fn unlimited_vehicles_are_created_on_demand() {
+    let mut problem = instance(json!({
+      "vehicle_definitions": [
+        { "count": "unlimited", "fixed_cost": 10 },
+        { "count": 2, "capacity": 20, "fixed_cost": 10 },
+      ],
+    }), &[0.0, 1.0, 2.0, 3.0]);
+
+    /* The limited vehicles go first, then the first vehicle of the unlimited definition */
+    assert_eq!(problem.vehicles.len(), 3);
+    assert_eq!(problem.next_vehicle(1), None);
+    assert_eq!(problem.next_vehicle(2), Some(3));
+    assert_eq!(problem.vehicle(5).vehicle_type, 0);
+    assert_eq!(problem.idle_vehicles(&HashSet::from([0, 2, 3])), vec![1, 4]);
+    assert_eq!(problem.type_vehicles(), vec![vec![2, 3, 4], vec![0, 1]]);
+
+    problem.add_created_vehicles(vec![2, 4, 4, 1].into_iter());
+    assert_eq!(problem.vehicles.iter().map(|vehicle| vehicle.id).collect::<Vec<_>>(), vec![0, 1, 2, 4]);
+  }
+
+  #[test]
+  fn postponed_departures_leave_the_source_later() {
+    let problem = instance(json!({ "clients": [{}, { "earliest": 100, "max_ride_time": 50 }] }), &[0.0, 10.0]);
+
+    let visits: Vec<_> = vec![0, 1, 0].into_iter().map(|client_id| problem.visit(client_id)).collect();
+    let route = problem.build_route(0, &visits).unwrap();
+
+    assert_eq!(route.clients[0].leave_time, 90.0);
+    assert_eq!(route.clients[1].trip_start, 90.0);
+    assert_eq!(route.route_duration, 20.0);
+  }
+
+  #[test]
+  fn departures_postponed_past_the_break_window_rest_at_the_source() {
+    let problem = instance(json!({
+      "vehicle_definitions": [{ "break_window": [20, 40], "break_duration": 10 }],
+      "clients": [{}, { "earliest": 100, "max_ride_time": 50 }],
+    }), &[0.0, 10.0]);
+
+    let visits: Vec<_> = vec![0, 1, 0].into_iter().map(|client_id| problem.visit(client_id)).collect();
+    let route = problem.build_route(0, &visits).unwrap();
+
+    assert!(route.clients[1].is_break);
+    assert_eq!(route.clients[1].arrive_time, 20.0);
+    assert_eq!(route.clients[2].arrive_time, 100.0);
+  }
+
+  #[test]
+  fn drivers_busy_past_the_break_window_rest_before_leaving() {
+    let problem = instance(json!({
+      "vehicle_definitions": [{ "break_window": [30, 60], "break_duration": 10 }],
+      "clients": [{}, { "service_time": 10, "earliest": 70 }],
+    }), &[0.0, 10.0]);
+
+    let visits: Vec<_> = vec![0, 1, 0].into_iter().map(|client_id| problem.visit(client_id)).collect();
+    let route = problem.build_route(0, &visits).unwrap();
+
+    assert!(route.clients[1].is_break);
+    assert_eq!(route.clients[1].arrive_time, 30.0);
+    assert_eq!(route.clients[2].arrive_time, 70.0);
+  }
+
+  #[test]
+  fn backhauls_follow_the_linehauls_of_their_trip() {
+    let mut problem = instance(json!({
+      "vehicle_definitions": [{ "max_trips": 2 }],
+      "clients": [{}, { "demand": 5 }, { "demand": 5, "kind": "backhaul" }],
+    }), &[0.0, 1.0, 2.0]);
+    let load_feasible = |problem: &ProblemInstance, clients: Vec<usize>| {
+      let visits: Vec<_> = clients.into_iter().map(|client_id| problem.visit(client_id)).collect();
+      problem.is_load_feasible(0, visits.iter())
+    };
+
+    assert!(load_feasible(&problem, vec![0, 1, 2, 0]));
+    assert!(!load_feasible(&problem, vec![0, 2, 1, 0]));
+    assert!(load_feasible(&problem, vec![0, 2, 0, 1, 0]));
+
+    problem.mixed_backhauls = true;
+    assert!(load_feasible(&problem, vec![0, 2, 1, 0]));
+  }
+}
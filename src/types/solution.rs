@@ -1,27 +1,62 @@
+use std::cmp::Ordering;
 use std::fmt;
 use serde::{Serialize};
 
 use super::others::{RouteEntry, Cost, Time};
 
+///
+/// Clients served of a priority class, and served within their time window.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceLevel {
+  pub priority: u32,
+  pub clients: usize,
+  pub served: usize,
+  pub on_time: usize,
+}
+
+impl fmt::Display for ServiceLevel {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "priority {}: {}/{} served, {} on time", self.priority, self.served, self.clients, self.on_time)
+  }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Solution {
   pub routes: Vec<RouteEntry>,
+  pub unserved_clients: Vec<usize>,
+  pub unserved_penalty: Cost,
+  /* vehicles used of each vehicle definition */
+  pub fleet_mix: Vec<usize>,
+  /* from the highest priority class to the lowest */
+  pub service_levels: Vec<ServiceLevel>,
   pub value: Cost,
   pub construction_value: Cost,
   pub distance: Time,
+  pub fixed_cost: Cost,
+  /* time served after the latest of each client */
+  pub lateness: Time,
   pub iter_found: i32,
   pub weight_config_name: String,
+  /* whether the construction left clients that must be served unrouted */
+  pub repaired: bool,
 }
 
 impl Default for Solution {
   fn default() -> Solution {
     Solution {
       routes: vec![],
+      unserved_clients: vec![],
+      unserved_penalty: 0 as Cost,
+      fleet_mix: vec![],
+      service_levels: vec![],
       distance: 0 as Time,
+      fixed_cost: 0 as Cost,
+      lateness: 0 as Time,
       value: (1 << 31) as Cost,
       construction_value: 0 as Cost,
       iter_found: 0,
       weight_config_name: "unset".to_string(),
+      repaired: false,
     }
   }
 }
@@ -34,14 +69,30 @@ impl fmt::Display for Solution {
   value: {}
   construction_value: {}
   distance: {}
+  fixed cost: {}
+  lateness: {}
+  routes count: {}
+  unserved clients: {}
+  unserved penalty: {}
+  fleet mix: {}
+  service levels: {}
   grsap config name: {}
   found at iter: {}
+  repaired: {}
   routes:\n{}",
       self.value,
       self.construction_value,
       self.distance,
+      self.fixed_cost,
+      self.lateness,
+      self.routes.len(),
+      self.unserved_clients.iter().map(|client_id| client_id.to_string()).collect::<Vec<String>>().join(", "),
+      self.unserved_penalty,
+      self.fleet_mix.iter().map(|count| count.to_string()).collect::<Vec<String>>().join("/"),
+      self.service_levels.iter().map(|level| level.to_string()).collect::<Vec<String>>().join("; "),
       self.weight_config_name,
       self.iter_found,
+      self.repaired,
       self.routes.iter().map(|route| format!("{}", route)).collect::<Vec<String>>().join("\n")
     )
   }
@@ -51,4 +102,102 @@ impl Solution {
   pub fn total_route_time(&self) -> Time {
     self.routes.iter().map(|route| route.route_time).sum()
   }
+
+  ///
+  /// Compares the clients served of each priority class above the lowest one, from the highest,
+  /// and then the value.
+  pub fn is_better_than(&self, other: &Solution) -> bool {
+    let served = |sol: &Solution| -> Vec<usize> {
+      let levels = sol.service_levels.len().max(1) - 1;
+      sol.service_levels[..levels].iter().map(|level| level.served).collect()
+    };
+
+    match served(self).cmp(&served(other)) {
+      Ordering::Greater => true,
+      Ordering::Less => false,
+      Ordering::Equal => self.value < other.value,
+    }
+  }
+
+  ///
+  /// Objectives of the multi objective mode, all of them minimized.
+  pub fn objectives(&self) -> [f64; 5] {
+    [self.fixed_cost, self.distance, self.lateness, self.routes.len() as f64, self.unserved_penalty]
+  }
+
+  ///
+  /// Whether the solution is no worse than other on every objective and better on some.
+  pub fn dominates(&self, other: &Solution) -> bool {
+    let objectives = self.objectives();
+    let other_objectives = other.objectives();
+    let pairs = || objectives.iter().zip(other_objectives.iter());
+
+    pairs().all(|(value, other_value)| value <= other_value)
+    && pairs().any(|(value, other_value)| value < other_value)
+  }
+}
+
+///
+/// Solutions not dominated by any other solution added.
+#[derive(Debug, Default)]
+pub struct ParetoArchive {
+  solutions: Vec<Solution>,
+}
+
+impl ParetoArchive {
+  ///
+  /// Adds the solution unless it's dominated or has the same objectives than an archived one,
+  /// removing the solutions it dominates. Returns whether it was added.
+  pub fn add(&mut self, sol: Solution) -> bool {
+    let rejected = self.solutions.iter().any(|archived| {
+      archived.dominates(&sol) || archived.objectives() == sol.objectives()
+    });
+
+    if rejected {
+      return false
+    }
+
+    self.solutions.retain(|archived| !sol.dominates(archived));
+    self.solutions.push(sol);
+
+    true
+  }
+
+  pub fn merge(&mut self, other: ParetoArchive) {
+    for sol in other.solutions {
+      self.add(sol);
+    }
+  }
+
+  ///
+  /// Archived solutions sorted by value.
+  pub fn into_solutions(self) -> Vec<Solution> {
+    let mut solutions = self.solutions;
+    solutions.sort_by(|s1, s2| s1.value.partial_cmp(&s2.value).unwrap());
+
+    solutions
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn solution(fixed_cost: Cost, distance: Time) -> Solution {
+    Solution { fixed_cost, distance, value: fixed_cost + distance, ..Default::default() }
+  }
+
+  #[test]
+  fn pareto_archive_keeps_the_solutions_not_dominated() {
+    let mut archive = ParetoArchive::default();
+
+    assert!(archive.add(solution(10.0, 50.0)));
+    assert!(archive.add(solution(20.0, 30.0)));
+    assert!(!archive.add(solution(20.0, 60.0)));
+    assert!(!archive.add(solution(10.0, 50.0)));
+    assert!(archive.add(solution(10.0, 30.0)));
+
+    let objectives: Vec<[f64; 5]> = archive.into_solutions().iter().map(|sol| sol.objectives()).collect();
+    assert_eq!(objectives, vec![solution(10.0, 30.0).objectives()]);
+  }
 }
@@ -0,0 +1,42 @@
+use serde_json::{json, Value};
+
+use super::{Objective, ProblemInstance};
+
+///
+/// Instance initialized to optimize cost with the source, client 0, and a client on each other position. The
+/// distances are the absolute differences between the positions on a line. Every client demands 10 with a wide
+/// time window, and one vehicle of capacity 10 serves them. The overrides replace the top level entries, and the
+/// entries of vehicle_definitions and clients are merged by index over the defaults.
+pub fn instance(overrides: Value, positions: &[f64]) -> ProblemInstance {
+  let distances: Vec<Vec<f64>> = positions.iter()
+    .map(|from| positions.iter().map(|to| (from - to).abs()).collect())
+    .collect();
+
+  let vehicle_definitions = overrides["vehicle_definitions"].as_array().cloned().unwrap_or_else(|| vec![json!({})]);
+  let clients = overrides["clients"].as_array().cloned().unwrap_or_default();
+
+  let mut value = merge(json!({ "distances": distances }), &overrides);
+  value["vehicle_definitions"] = vehicle_definitions.iter()
+    .map(|vehicle_def| merge(json!({ "count": 1, "capacity": 10, "fixed_cost": 0, "variable_cost": 1 }), vehicle_def))
+    .collect();
+  value["clients"] = (0..positions.len())
+    .map(|client_id| {
+      let demand = if client_id == 0 { 0 } else { 10 };
+      let client = json!({ "demand": demand, "service_time": 0, "earliest": 0, "latest": 1000, "pos": [0, 0] });
+      merge(client, clients.get(client_id).unwrap_or(&json!({})))
+    })
+    .collect();
+
+  let mut problem: ProblemInstance = serde_json::from_value(value).unwrap();
+  problem.init(Objective::cost());
+
+  problem
+}
+
+fn merge(mut value: Value, overrides: &Value) -> Value {
+  for (key, entry) in overrides.as_object().into_iter().flatten() {
+    value[key] = entry.clone();
+  }
+
+  value
+}
@@ -101,7 +101,7 @@ vehicle_type_regex = re.compile(
 )
 
 
-def extract_vehicle_definitions(content):
+def extract_vehicle_definitions(content, unlimited_fleet=False):
     """
     Obtain vehicle definitions in the form:
       q=[200 100];
@@ -144,7 +144,7 @@ def extract_vehicle_definitions(content):
             vehicle_count = sum(map(int, type_array.split()))
 
             ret.append({
-                "count": vehicle_count + 2,
+                "count": "unlimited" if unlimited_fleet else vehicle_count + 2,
                 "capacity": capacities[index],
                 "fixed_cost": fixed_costs[index],
                 "variable_cost": variable_costs[index],
@@ -159,7 +159,7 @@ def extract_vehicle_definitions(content):
     return ret
 
 
-def export_file_to_config(filename, allowed_deviation, deviation_penalty, suffix=''):
+def export_file_to_config(filename, allowed_deviation, deviation_penalty, suffix='', unlimited_fleet=False):
     """
     Generates a configuration file from a CIPLEX .m source
     """
@@ -175,7 +175,8 @@ def export_file_to_config(filename, allowed_deviation, deviation_penalty, suffix
             filter(lambda line: not line.startswith('%'), list(file)))
 
         clients, distances = extract_graph(content)
-        vehicle_definitions = extract_vehicle_definitions(content)
+        vehicle_definitions = extract_vehicle_definitions(
+            content, unlimited_fleet)
 
         with open(outputname, 'w') as out:
             out.write(json.dumps({
@@ -196,6 +197,8 @@ def main(argv):
     parser.add_argument('--allowed-deviation', type=float, default=0.5)
     parser.add_argument('--deviation-penalty', type=float, default=0.1)
     parser.add_argument('--suffix', default='')
+    parser.add_argument('--unlimited-fleet', action='store_true',
+                        help='Let the solver use as many vehicles of each type as needed')
 
     args = parser.parse_args(argv)
 
@@ -206,6 +209,7 @@ def main(argv):
                 args.allowed_deviation,
                 args.deviation_penalty,
                 suffix=args.suffix,
+                unlimited_fleet=args.unlimited_fleet,
             )
         except InfoNotFoundError as e:
             log_err("Error in file {}: {}".format(filename, e))
@@ -48,8 +48,10 @@ def process_solution(data):
         distance=solution.get('distance'),
         solution_value=solution.get('value'),
         number_of_vehicles=len(solution.get('routes')),
+        fleet_mix='/'.join(map(str, solution.get('fleet_mix', []))),
         max_wait_time=get_max_wait_time(solution),
         iter_found=solution.get('iter_found'),
+        repaired_iters=data.get('repaired_iters', 0),
     )
 
 
@@ -28,6 +28,81 @@ class Errors:
         return '\n'.join(lines)
 
 
+def as_load(value):
+    if isinstance(value, list):
+        return value
+    return [value]
+
+
+def travel_time(profile, distance, speed, departure_time):
+    """
+    Travel time through the speed profile buckets, mirrors SpeedProfile::travel_time
+    """
+    if profile is None:
+        return distance / speed
+
+    times = profile.get('times')
+    speeds = profile.get('speeds')
+    bucket = max(len([t for t in times if t <= departure_time]), 1) - 1
+    distance_left = distance
+    current_time = departure_time
+
+    while bucket + 1 < len(times):
+        bucket_speed = speed * speeds[bucket]
+        bucket_distance = (times[bucket + 1] - current_time) * bucket_speed
+        if bucket_distance >= distance_left:
+            break
+        distance_left -= bucket_distance
+        current_time = times[bucket + 1]
+        bucket += 1
+
+    return current_time + distance_left / (speed * speeds[bucket]) - departure_time
+
+
+def arc_speed_profile(instance, client_from_id, client_to_id):
+    speed_profiles = instance.get('speed_profiles') or []
+    arc_speed_profiles = instance.get('arc_speed_profiles') or []
+
+    if not speed_profiles:
+        return None
+    if not arc_speed_profiles:
+        return speed_profiles[0]
+
+    return speed_profiles[arc_speed_profiles[client_from_id][client_to_id]]
+
+
+def validate_trip_load(errors, instance, capacity, trip, trip_number):
+    """
+    Checks the load fits the capacity on every visit of the trip, leaving the source with the
+    linehaul demands and picking up the backhaul ones after the linehauls unless they are mixed
+    """
+    def check_load(load):
+        for dimension, (value, limit) in enumerate(zip(load, capacity)):
+            errors.assert_cond(
+                value <= limit,
+                f'Capacity dimension {dimension} overpassed by {value - limit} on trip {trip_number}'
+            )
+
+    load = [0] * len(capacity)
+    for kind, delivered in trip:
+        if kind == 'linehaul':
+            load = [value + other for value, other in zip(load, delivered)]
+    check_load(load)
+
+    backhaul_visited = False
+    for kind, delivered in trip:
+        if kind == 'linehaul':
+            errors.assert_cond(
+                not backhaul_visited or instance.get('mixed_backhauls', False),
+                f'Linehaul visited after a backhaul on trip {trip_number}'
+            )
+            load = [value - other for value, other in zip(load, delivered)]
+        else:
+            backhaul_visited = True
+            load = [value + other for value, other in zip(load, delivered)]
+            check_load(load)
+
+
 def validate_solution(data):
     instance = data.get('instance')
     distances = instance.get('distances')
@@ -36,28 +111,123 @@ def validate_solution(data):
     solution = data.get('solution')
     routes = solution.get('routes')
     allowed_deviation = instance.get('allowed_deviation')
+    allowed_early_deviation = instance.get('allowed_early_deviation', 0)
+    early_deviation_penalty = instance.get('early_deviation_penalty', 0)
 
     errors = Errors(data.get('name'))
+    delivered_by_client = {
+        client_id: [0] * len(as_load(client.get('demand')))
+        for client_id, client in clients.items()
+    }
 
+    objective = instance.get('objective')
     val = 0
     for route in routes:
         route_clients = route.get('clients')
         vehicle_id = route.get('vehicle_id')
         vehicle = vehicles.get(vehicle_id)
-        val += vehicle.get('fixed_cost')
-        capacity_left = vehicle.get('capacity')
-        current_time = 0
+        route_distance = 0
+        route_wait_cost = 0
+        route_early_cost = 0
+        route_lateness = 0
+        capacity = as_load(vehicle.get('capacity'))
+        trip = []
+        speed_factor = vehicle.get('speed_factor', 1)
+        service_time_factor = vehicle.get('service_time_factor', 1)
+        source = instance.get('source')
+        trips = 0
+        max_driving_time = vehicle.get('max_driving_time')
+        break_window = vehicle.get('break_window')
+        break_taken = False
+        wait_cost = vehicle.get('wait_cost', 0)
+        # Serving early only pays off when it's cheaper than waiting
+        serve_early = objective.get('early') * early_deviation_penalty < wait_cost * \
+            objective.get('waiting')
+        driving_time = 0
+        current_time = route_clients[0].get('leave_time')
+        trip_start = current_time
 
         route_errors = Errors(f'Vehicle {vehicle_id}')
-        for c1, c2 in zip(route_clients[:-1], route_clients[1:]):
+        route_errors.assert_cond(
+            route_clients[0].get('arrive_time') >= (vehicle.get('shift_start') or 0),
+            f"Route starts at {route_clients[0].get('arrive_time')} before the shift"
+        )
+
+        c1 = route_clients[0]
+        for c2 in route_clients[1:]:
+            if c2.get('is_break'):
+                break_start = c2.get('arrive_time')
+                route_errors.assert_cond(
+                    break_start >= current_time,
+                    f"Break at {c2.get('client_id')} starts at {break_start} before leaving at {current_time}"
+                )
+                route_errors.assert_cond(
+                    c2.get('leave_time') - break_start >= vehicle.get('break_duration') - 1e-6,
+                    f"Break at {c2.get('client_id')} is shorter than {vehicle.get('break_duration')}"
+                )
+                route_wait_cost += (break_start - current_time) * wait_cost
+                if break_window and break_window[0] <= break_start <= break_window[1]:
+                    break_taken = True
+                driving_time = 0
+                current_time = c2.get('leave_time')
+                continue
+
             client2 = clients.get(c2.get('client_id'))
-            arc_time = distances[c1.get('client_id')][c2.get('client_id')]
-            current_time = max(client2.get('earliest'),
-                               arc_time + current_time)
-            allowed_offset = (client2.get('latest') -
-                              client2.get('earliest')) * allowed_deviation
+            missing_skills = set(client2.get('required_skills', [])) - \
+                set(vehicle.get('skills', []))
+            route_errors.assert_cond(
+                not missing_skills,
+                f"Client {client2.get('id')} requires skills {sorted(missing_skills)}"
+            )
+            route_errors.assert_cond(
+                vehicle.get('vehicle_type') not in client2.get(
+                    'forbidden_vehicle_types', []),
+                f"Client {client2.get('id')} forbids vehicle type {vehicle.get('vehicle_type')}"
+            )
+            distance = distances[c1.get('client_id')][c2.get('client_id')]
+            profile = arc_speed_profile(
+                instance, c1.get('client_id'), c2.get('client_id'))
+            arc_time = travel_time(
+                profile, distance, speed_factor, current_time)
+            max_ride_times = [value for value in [
+                client2.get('max_ride_time'), vehicle.get('max_ride_time')] if value is not None]
+            window = client2.get('latest') - client2.get('earliest')
+            service_earliest = client2.get('earliest')
+            if serve_early:
+                service_earliest -= window * allowed_early_deviation
+            if c1.get('client_id') == source:
+                # Vehicles with ride limits leave the source as late as the time window allows
+                if max_ride_times and service_earliest - arc_time > current_time:
+                    current_time = service_earliest - arc_time
+                    arc_time = travel_time(
+                        profile, distance, speed_factor, current_time)
+                trip_start = current_time
+            route_errors.assert_cond(
+                not max_driving_time or driving_time == 0 or
+                driving_time + arc_time <= max_driving_time,
+                f"Driving {driving_time + arc_time} without a break to {client2.get('id')}"
+            )
+            route_errors.assert_cond(
+                not break_window or break_taken or
+                current_time + arc_time <= break_window[1],
+                f"Break not taken before arriving to {client2.get('id')}"
+            )
+            driving_time += arc_time
+            arrival_time = arc_time + current_time
+            current_time = max(service_earliest, arrival_time)
+            route_wait_cost += (current_time - arrival_time) * wait_cost
+            route_early_cost += max(client2.get('earliest') - current_time, 0) * \
+                early_deviation_penalty
+            route_lateness += max(current_time - client2.get('latest'), 0)
+            allowed_offset = window * allowed_deviation
             client_latest = client2.get('latest') + allowed_offset
-            capacity_left -= client2.get('demand')
+            delivered = as_load(c2.get('delivered', client2.get('demand')))
+            delivered_by_client[client2.get('id')] = [
+                total + value for total, value in
+                zip(delivered_by_client[client2.get('id')], delivered)
+            ]
+            if client2.get('id') != source:
+                trip.append((client2.get('kind', 'linehaul'), delivered))
             arrive_time = c2.get('arrive_time')
 
             route_errors.assert_cond(
@@ -69,13 +239,90 @@ def validate_solution(data):
                 f"Client {client2.get('id')} arrival time is {current_time} but latest is {client_latest}"
             )
 
-            current_time += client2.get('service_time')
-            val += arc_time
+            route_errors.assert_cond(
+                client2.get('id') == source or
+                all(current_time - trip_start <= value for value in max_ride_times),
+                f"Client {client2.get('id')} is served {current_time - trip_start} after leaving the source, max ride time is {max_ride_times}"
+            )
+
+            current_time += client2.get('service_time') * service_time_factor
+            route_distance += distance
+
+            if client2.get('id') == source and vehicle.get('shift_end') is not None:
+                route_errors.assert_cond(
+                    c2.get('arrive_time') - c2.get('wait_time') <= vehicle.get('shift_end'),
+                    f"Vehicle arrives to the source at {c2.get('arrive_time')} after the shift"
+                )
+
+            # Each trip ends at the source where the vehicle is reloaded
+            if client2.get('id') == source and c1.get('client_id') != source:
+                trips += 1
+                current_time += vehicle.get('reload_time', 0)
+                validate_trip_load(route_errors, instance, capacity, trip, trips)
+                trip = []
+
+            c1 = c2
+
+        route_duration = route_clients[-1].get('arrive_time') - \
+            route_clients[0].get('leave_time')
+        val += objective.get('fixed_cost') * vehicle.get('fixed_cost') + \
+            objective.get('variable_cost') * vehicle.get('variable_cost') * route_distance + \
+            objective.get('distance') * route_distance + \
+            objective.get('duration') * route_duration + \
+            objective.get('waiting') * route_wait_cost + \
+            objective.get('lateness') * route_lateness + \
+            objective.get('early') * route_early_cost + \
+            objective.get('routes')
 
         route_errors.assert_cond(
-            capacity_left >= 0, f'Capacity overpassed by {-capacity_left} on vehicle {vehicle_id}')
+            trips <= vehicle.get('max_trips', 1),
+            f"Vehicle does {trips} trips but max trips is {vehicle.get('max_trips', 1)}"
+        )
         errors.assert_cond(not route_errors, route_errors)
 
+    served = [c.get('client_id') for route in routes
+              for c in route.get('clients')
+              if c.get('client_id') != instance.get('source') and not c.get('is_break')]
+    unserved = solution.get('unserved_clients', [])
+    for client_id in unserved:
+        prize = clients.get(client_id).get('prize')
+        errors.assert_cond(
+            prize is not None, f"Client {client_id} is not optional but it's unserved")
+        val += prize or 0
+
+    split_deliveries = instance.get('split_deliveries', False)
+    for client_id, client in clients.items():
+        if client_id == instance.get('source'):
+            continue
+        times_visited = served.count(client_id) + unserved.count(client_id)
+        if split_deliveries and client_id not in unserved:
+            errors.assert_cond(
+                times_visited >= 1,
+                f"Client {client_id} expected to be visited or unserved"
+            )
+        else:
+            errors.assert_cond(
+                times_visited == 1,
+                f"Client {client_id} expected to be visited once or unserved, found {times_visited}"
+            )
+        if client_id not in unserved:
+            errors.assert_cond(
+                all(abs(total - demand) < 1e-6 for total, demand in
+                    zip(delivered_by_client[client_id], as_load(client.get('demand')))),
+                f"Client {client_id} got {delivered_by_client[client_id]} delivered but its demand is {client.get('demand')}"
+            )
+
+    for level in solution.get('service_levels', []):
+        priority_clients = [
+            client_id for client_id, client in clients.items()
+            if client_id != instance.get('source') and client.get('priority', 0) == level.get('priority')
+        ]
+        served_count = len([c for c in priority_clients if c in served])
+        errors.assert_cond(
+            level.get('served') == served_count and level.get('clients') == len(priority_clients),
+            f"Priority {level.get('priority')} serves {served_count} of {len(priority_clients)} clients but found {level}"
+        )
+
     solution_val = solution.get('value')
     errors.assert_cond(
         val == solution_val, f"Expected solution value of {val}, found {solution_val}")
@@ -90,6 +337,12 @@ def validate_solution_from_file(filename):
         if errors:
             log_err(f'{errors.print()}\n')
 
+        for index, solution in enumerate(data.get('pareto_front', [])):
+            errors = validate_solution(dict(
+                data, name=f"{data.get('name')} pareto solution {index}", solution=solution))
+            if errors:
+                log_err(f'{errors.print()}\n')
+
 
 def main(args):
     if not args: